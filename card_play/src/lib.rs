@@ -440,10 +440,27 @@ impl Cards {
     /// assert_ne!(deck, ref_deck);
     /// ```
     pub fn shuffle_fy(&mut self) {
+        self.shuffle_fy_with(&mut rand::thread_rng());
+    }
+
+    /// Perform Fisher-Yates randomization (see shuffle_fy()) using the random number generator
+    /// provided, e.g. a seeded generator for a reproducible shuffle
+    ///
+    /// # Examples
+    /// ```
+    /// use card_play::{Cards, JokersPerDeck};
+    /// use rand::rngs::StdRng;
+    /// use rand::SeedableRng;
+    /// let mut deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+    /// let mut same_deck = deck.clone();
+    /// deck.shuffle_fy_with(&mut StdRng::seed_from_u64(42));
+    /// same_deck.shuffle_fy_with(&mut StdRng::seed_from_u64(42));
+    /// assert_eq!(deck, same_deck);
+    /// ```
+    pub fn shuffle_fy_with(&mut self, rng: &mut impl Rng) {
         // Fisher-Yates algo from Wikipedia
-        let mut rng = rand::thread_rng();
         let n = self.0.len();
-        for i in 0..(n - 2) {
+        for i in 0..n.saturating_sub(1) {
             self.0.swap(i, rng.gen_range(i..n));
         }
     }
@@ -596,7 +613,7 @@ impl Cards {
             .0
             .iter()
             .enumerate()
-            .filter(|(_, r)| (**r == card))
+            .filter(|(_, r)| **r == card)
             .map(|(idx, _)| idx)
             .nth(match_index)
         else {
@@ -637,7 +654,8 @@ impl Cards {
             .0
             .iter()
             .enumerate()
-            .filter_map(|(idx, r)| (*r == card).then(|| idx))
+            .filter(|(_, r)| **r == card)
+            .map(|(idx, _)| idx)
             .nth(match_index)
         else {
             return false;
@@ -962,6 +980,27 @@ mod tests {
         assert_eq!((mean + 0.5) as usize, deck_size / 2);
     }
 
+    #[test]
+    fn test_fs_shuffle_reaches_every_order() {
+        // all 6 orders of 3 cards should turn up (the last two cards must be swappable too)
+        let mut seen: Vec<Cards> = Vec::new();
+        for _ in 0..1000 {
+            let mut deck = Cards(vec![
+                Card::Ace(Suit::Heart),
+                Card::Two(Suit::Heart),
+                Card::Three(Suit::Heart),
+            ]);
+            deck.shuffle_fy();
+            if !seen.contains(&deck) {
+                seen.push(deck);
+            }
+        }
+        assert_eq!(seen.len(), 6);
+        let mut empty = Cards(vec![]);
+        empty.shuffle_fy();
+        assert_eq!(empty.len(), 0);
+    }
+
     #[test]
     fn test_riffle_shuffles_and_fs_shuffle_statistics() {
         const ITER_COUNT: usize = 1000;
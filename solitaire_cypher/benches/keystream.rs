@@ -0,0 +1,28 @@
+//! Compare KeyStream generation by the Cards based get_key_stream() with the array based
+//! DeckState::key_stream()
+
+use card_play::{Cards, JokersPerDeck};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use solitaire_cypher::deck_state::DeckState;
+use solitaire_cypher::get_key_stream;
+
+fn bench_key_stream(c: &mut Criterion) {
+    let mut deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+    deck.shuffle_fy();
+    let state = DeckState::from_cards(&deck).unwrap();
+
+    let mut group = c.benchmark_group("key_stream");
+    for len in [100usize, 10_000] {
+        group.throughput(Throughput::Elements(len as u64));
+        group.bench_with_input(BenchmarkId::new("cards", len), &len, |b, len| {
+            b.iter(|| get_key_stream(black_box(deck.clone()), *len))
+        });
+        group.bench_with_input(BenchmarkId::new("deck_state", len), &len, |b, len| {
+            b.iter(|| black_box(state).key_stream(*len).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_key_stream);
+criterion_main!(benches);
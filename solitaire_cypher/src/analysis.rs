@@ -0,0 +1,410 @@
+//! # Analysis
+//!
+//! Empirical study of the Solitaire deck state machine: how many steps a key deck takes before
+//! its deck state (and so its KeyStream) repeats.  Cycle detection uses Brent's algorithm on the
+//! compact DeckState so only a couple of deck states are ever held in memory.
+//!
+//! Note that with 54! possible deck states the period of a full deck is expected to be far beyond
+//! any practical step limit, in which case the cycle is reported as not found.  Reduced decks (see
+//! deck_state module) are small enough to find cycles for, and the smallest small enough for
+//! their whole state space to be explored (see explore_state_space()).
+
+use crate::deck_state::{DeckState, MIN_DECK_SIZE};
+use card_play::{Cards, JokersPerDeck};
+use rand::seq::SliceRandom;
+use std::fmt;
+use std::fmt::Display;
+
+/// The cycle a deck state sequence falls into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycle {
+    /// Number of steps before the first deck state that is part of the cycle
+    pub pre_period: u64,
+    /// Number of steps for a deck state within the cycle to repeat
+    pub period: u64,
+}
+
+/// Find the period and pre-period of the deck states produced by repeated next_deck_state()
+/// steps from the key deck.  None if the Cards are not a complete deck with both jokers or if
+/// the cycle isn't found within max_steps steps.
+///
+/// # Examples
+/// ```
+/// use card_play::{Cards, JokersPerDeck};
+/// use solitaire_cypher::analysis::find_cycle;
+/// let deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+/// // the period of a full deck is far too long to find
+/// assert_eq!(find_cycle(&deck, 10_000), None);
+/// ```
+pub fn find_cycle(key_deck: &Cards, max_steps: u64) -> Option<Cycle> {
+    find_state_cycle(DeckState::from_cards(key_deck)?, max_steps)
+}
+
+/// Find the period and pre-period of the deck states produced by repeated DeckState::step()
+/// steps from a deck of any size.  None if the cycle isn't found within max_steps steps.
+///
+/// # Examples
+/// ```
+/// use solitaire_cypher::analysis::{find_state_cycle, Cycle};
+/// use solitaire_cypher::deck_state::DeckState;
+/// let deck = DeckState::<6, 26>::new_deck();
+/// assert_eq!(find_state_cycle(deck, 1000), Some(Cycle { pre_period: 111, period: 39 }));
+/// ```
+pub fn find_state_cycle<const N: usize, const M: u8>(
+    start: DeckState<N, M>,
+    max_steps: u64,
+) -> Option<Cycle> {
+    brent(
+        start,
+        |mut state| {
+            state.step();
+            state
+        },
+        max_steps,
+    )
+}
+
+/// Distribution of the cycles found from a number of key decks
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CycleSummary {
+    /// Number of key decks sampled
+    pub samples: usize,
+    /// Number of key decks whose cycle was found within the step limit
+    pub found: usize,
+    /// Shortest period found
+    pub min_period: Option<u64>,
+    /// Longest period found
+    pub max_period: Option<u64>,
+    /// Mean of the periods found
+    pub mean_period: Option<f64>,
+    /// Median of the periods found
+    pub median_period: Option<u64>,
+    /// Mean of the pre-periods found
+    pub mean_pre_period: Option<f64>,
+    /// Longest pre-period found
+    pub max_pre_period: Option<u64>,
+}
+
+impl CycleSummary {
+    /// Summarize a collection of cycle detection results (None for not found)
+    ///
+    /// # Examples
+    /// ```
+    /// use solitaire_cypher::analysis::{Cycle, CycleSummary};
+    /// let results = vec![
+    ///     Some(Cycle { pre_period: 0, period: 10 }),
+    ///     None,
+    ///     Some(Cycle { pre_period: 4, period: 30 }),
+    /// ];
+    /// let summary = CycleSummary::from_results(&results);
+    /// assert_eq!((summary.samples, summary.found), (3, 2));
+    /// assert_eq!(summary.mean_period, Some(20.0));
+    /// assert_eq!(summary.max_pre_period, Some(4));
+    /// ```
+    pub fn from_results(results: &[Option<Cycle>]) -> CycleSummary {
+        let found: Vec<&Cycle> = results.iter().flatten().collect();
+        let mut summary = CycleSummary {
+            samples: results.len(),
+            found: found.len(),
+            ..Default::default()
+        };
+        if found.is_empty() {
+            return summary;
+        }
+        let mut periods: Vec<u64> = found.iter().map(|c| c.period).collect();
+        periods.sort_unstable();
+        summary.min_period = periods.first().copied();
+        summary.max_period = periods.last().copied();
+        summary.median_period = Some(periods[periods.len() / 2]);
+        summary.mean_period = Some(periods.iter().sum::<u64>() as f64 / periods.len() as f64);
+        summary.mean_pre_period =
+            Some(found.iter().map(|c| c.pre_period).sum::<u64>() as f64 / found.len() as f64);
+        summary.max_pre_period = found.iter().map(|c| c.pre_period).max();
+        summary
+    }
+}
+
+impl Display for CycleSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |v: Option<u64>| v.map_or("-".to_string(), |v| v.to_string());
+        let show_f = |v: Option<f64>| v.map_or("-".to_string(), |v| format!("{:.1}", v));
+        writeln!(f, "samples:         {}", self.samples)?;
+        writeln!(f, "cycles found:    {}", self.found)?;
+        writeln!(f, "min period:      {}", show(self.min_period))?;
+        writeln!(f, "median period:   {}", show(self.median_period))?;
+        writeln!(f, "mean period:     {}", show_f(self.mean_period))?;
+        writeln!(f, "max period:      {}", show(self.max_period))?;
+        writeln!(f, "mean pre-period: {}", show_f(self.mean_pre_period))?;
+        write!(f, "max pre-period:  {}", show(self.max_pre_period))
+    }
+}
+
+/// Find the cycles of a number of random (Cards::shuffle_fy()) key decks, each limited to
+/// max_steps steps, and summarize them
+///
+/// # Examples
+/// ```
+/// use solitaire_cypher::analysis::sample_cycles;
+/// let summary = sample_cycles(3, 1000);
+/// assert_eq!(summary.samples, 3);
+/// println!("{}", summary);
+/// ```
+pub fn sample_cycles(samples: usize, max_steps: u64) -> CycleSummary {
+    let results: Vec<Option<Cycle>> = (0..samples)
+        .map(|_| {
+            // can panic if code broken - next line uses illegal joker count
+            let mut deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+            deck.shuffle_fy();
+            find_cycle(&deck, max_steps)
+        })
+        .collect();
+    CycleSummary::from_results(&results)
+}
+
+/// Find the cycles of a number of random decks of N cards, each limited to max_steps steps, and
+/// summarize them
+///
+/// # Examples
+/// ```
+/// use solitaire_cypher::analysis::sample_state_cycles;
+/// // a deck of 8 has only 40320 states so every cycle is found
+/// let summary = sample_state_cycles::<8>(20, 1_000_000);
+/// assert_eq!((summary.samples, summary.found), (20, 20));
+/// ```
+pub fn sample_state_cycles<const N: usize>(samples: usize, max_steps: u64) -> CycleSummary {
+    let results: Vec<Option<Cycle>> = (0..samples)
+        .map(|_| {
+            let mut values = *DeckState::<N>::new_deck().values();
+            values.shuffle(&mut rand::thread_rng());
+            // can panic if code broken - a shuffled deck is still a complete deck
+            find_state_cycle(DeckState::<N>::from_values(values).unwrap(), max_steps)
+        })
+        .collect();
+    CycleSummary::from_results(&results)
+}
+
+/// Largest deck explore_state_space() will take on (10! = 3,628,800 deck states)
+pub const MAX_EXPLORED_DECK_SIZE: usize = 10;
+
+/// The structure of every deck state of a reduced deck under repeated steps
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateSpace {
+    /// Number of cards in the deck
+    pub deck_size: usize,
+    /// Number of deck states, i.e. deck size factorial
+    pub states: u64,
+    /// Number of deck states no deck steps to, which can only ever be key decks
+    pub unreachable: u64,
+    /// Period of every cycle, longest first
+    pub cycles: Vec<u64>,
+    /// Number of deck states on a cycle, the only states which repeat
+    pub cyclic_states: u64,
+}
+
+impl Display for StateSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |v: Option<&u64>| v.map_or("-".to_string(), |v| v.to_string());
+        writeln!(f, "deck size:       {}", self.deck_size)?;
+        writeln!(f, "deck states:     {}", self.states)?;
+        writeln!(f, "unreachable:     {}", self.unreachable)?;
+        writeln!(f, "cycles:          {}", self.cycles.len())?;
+        writeln!(f, "cyclic states:   {}", self.cyclic_states)?;
+        writeln!(f, "longest cycle:   {}", show(self.cycles.first()))?;
+        write!(f, "shortest cycle:  {}", show(self.cycles.last()))
+    }
+}
+
+/// Step every deck state of a deck of N cards to find all of its cycles and the deck states which
+/// can't be reached.  None if N is over MAX_EXPLORED_DECK_SIZE (or under
+/// deck_state::MIN_DECK_SIZE).
+///
+/// # Examples
+/// ```
+/// use solitaire_cypher::analysis::explore_state_space;
+/// let space = explore_state_space::<5>().unwrap();
+/// assert_eq!(space.states, 120);
+/// assert_eq!(space.cycles.iter().sum::<u64>(), space.cyclic_states);
+/// println!("{}", space);
+/// ```
+pub fn explore_state_space<const N: usize>() -> Option<StateSpace> {
+    if !(MIN_DECK_SIZE..=MAX_EXPLORED_DECK_SIZE).contains(&N) {
+        return None;
+    }
+    let states = (1..=N as u64).product::<u64>();
+    // walk[i] is the number of the walk which first reached state i, 0 if none has
+    let mut walk = vec![0u32; states as usize];
+    let mut walk_step = vec![0u32; states as usize];
+    let mut reached = vec![false; states as usize];
+    let mut space = StateSpace {
+        deck_size: N,
+        states,
+        ..Default::default()
+    };
+    for start in 0..states {
+        let walk_number = start as u32 + 1;
+        let mut rank = start;
+        let mut state = unrank::<N>(start);
+        let mut step = 0u32;
+        while walk[rank as usize] == 0 {
+            walk[rank as usize] = walk_number;
+            walk_step[rank as usize] = step;
+            step += 1;
+            state.step();
+            rank = permutation_rank(state.values());
+            reached[rank as usize] = true;
+        }
+        // a cycle is found when a walk comes back on itself
+        if walk[rank as usize] == walk_number {
+            space
+                .cycles
+                .push(u64::from(step - walk_step[rank as usize]));
+        }
+    }
+    space.cycles.sort_unstable_by(|a, b| b.cmp(a));
+    space.cyclic_states = space.cycles.iter().sum();
+    space.unreachable = reached.iter().filter(|r| !**r).count() as u64;
+    Some(space)
+}
+
+// Lexicographic rank of a deck state among all the permutations of 1..=N
+fn permutation_rank(values: &[u8]) -> u64 {
+    let mut rank = 0;
+    for (i, value) in values.iter().enumerate() {
+        let smaller_after = values[i + 1..].iter().filter(|v| *v < value).count() as u64;
+        rank = rank * (values.len() - i) as u64 + smaller_after;
+    }
+    rank
+}
+
+// The deck state with the given permutation_rank()
+fn unrank<const N: usize>(mut rank: u64) -> DeckState<N> {
+    let mut remaining: Vec<u8> = (1..=N as u8).collect();
+    let mut values = [0u8; N];
+    let mut radix = (1..N as u64).product::<u64>();
+    for (i, value) in values.iter_mut().enumerate() {
+        let index = (rank / radix) as usize;
+        rank %= radix;
+        *value = remaining.remove(index);
+        if i + 1 < N {
+            radix /= (N - 1 - i) as u64;
+        }
+    }
+    // can panic if code broken - every value is used exactly once
+    DeckState::from_values(values).unwrap()
+}
+
+// Brent's cycle detection, None if more than max_steps steps would be needed
+fn brent<S: Copy + Eq>(start: S, step: impl Fn(S) -> S, max_steps: u64) -> Option<Cycle> {
+    let mut steps: u64 = 0;
+    let mut next = |state: S| -> Option<S> {
+        steps += 1;
+        (steps <= max_steps).then(|| step(state))
+    };
+
+    // find the period by having the tortoise wait at successive powers of two
+    let mut power: u64 = 1;
+    let mut period: u64 = 1;
+    let mut tortoise = start;
+    let mut hare = next(start)?;
+    while tortoise != hare {
+        if power == period {
+            tortoise = hare;
+            power *= 2;
+            period = 0;
+        }
+        hare = next(hare)?;
+        period += 1;
+    }
+
+    // find the pre-period with the hare a period ahead of the tortoise
+    let mut tortoise = start;
+    let mut hare = start;
+    for _ in 0..period {
+        hare = next(hare)?;
+    }
+    let mut pre_period = 0;
+    while tortoise != hare {
+        tortoise = next(tortoise)?;
+        hare = next(hare)?;
+        pre_period += 1;
+    }
+    Some(Cycle { pre_period, period })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_brent() {
+        // 0 -> 1 -> 2 -> 3 -> 4 -> 5 -> 6 -> 2 ...
+        let step = |x: u32| if x == 6 { 2 } else { x + 1 };
+        assert_eq!(
+            brent(0u32, step, 1000),
+            Some(Cycle {
+                pre_period: 2,
+                period: 5
+            })
+        );
+        assert_eq!(brent(0u32, step, 5), None);
+        // a pure cycle of one
+        assert_eq!(
+            brent(7u32, |x| x, 10),
+            Some(Cycle {
+                pre_period: 0,
+                period: 1
+            })
+        );
+        // x^2 + 1 mod 255 from 3 checked by brute force
+        let step = |x: u32| (x * x + 1) % 255;
+        let mut seen = vec![3u32];
+        let mut x = 3;
+        let cycle = loop {
+            x = step(x);
+            if let Some(i) = seen.iter().position(|s| *s == x) {
+                break Cycle {
+                    pre_period: i as u64,
+                    period: (seen.len() - i) as u64,
+                };
+            }
+            seen.push(x);
+        };
+        assert_eq!(brent(3u32, step, 1000), Some(cycle));
+    }
+
+    #[test]
+    fn test_permutation_rank() {
+        for rank in 0..720 {
+            assert_eq!(permutation_rank(unrank::<6>(rank).values()), rank);
+        }
+        assert_eq!(unrank::<6>(0), DeckState::<6>::new_deck());
+        assert_eq!(unrank::<6>(719).values(), &[6, 5, 4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn test_explore_state_space() {
+        assert_eq!(explore_state_space::<2>(), None);
+        assert_eq!(explore_state_space::<11>(), None);
+        for space in [
+            explore_state_space::<3>().unwrap(),
+            explore_state_space::<7>().unwrap(),
+        ] {
+            assert_eq!(space.cyclic_states, space.cycles.iter().sum::<u64>());
+            assert!(space.cyclic_states + space.unreachable <= space.states);
+        }
+        // agrees with cycle detection from every deck state
+        let space = explore_state_space::<6>().unwrap();
+        assert_eq!(space.states, 720);
+        let mut periods = Vec::new();
+        for rank in 0..space.states {
+            let cycle = find_state_cycle(unrank::<6>(rank), 100_000).unwrap();
+            assert!(space.cycles.contains(&cycle.period));
+            if cycle.pre_period == 0 {
+                periods.push(cycle.period);
+            } else {
+                assert!(cycle.pre_period <= space.states - space.cyclic_states);
+            }
+        }
+        assert_eq!(periods.len() as u64, space.cyclic_states);
+    }
+}
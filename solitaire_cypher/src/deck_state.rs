@@ -0,0 +1,387 @@
+//! # Deck State
+//!
+//! Compact, fixed size (and Copy) representation of a Solitaire deck for work needing many
+//! millions of steps, e.g. cycle analysis and bulk KeyStream generation.  Each card is held as its
+//! card_play default value (1-52 new deck order, 53 Joker A, 54 Joker B) and steps are performed
+//! in place without any heap allocation.  The KeyStream produced is identical to that of
+//! KeystreamGenerator.
+//!
+//! The deck size N and alphabet modulus M are parameters, defaulting to the 54 cards and 26
+//! letters of Solitaire, so that reduced variants can be studied exhaustively (see analysis and
+//! recovery modules).  A deck of N cards holds the values 1..=N with N-1 as Joker A and N as
+//! Joker B (both jokers count N-1, as both count 53 in a full deck) and the output card value v
+//! gives the letter (v - 1) % M + 1, e.g. with 10 + 2 cards and modulus 10 the KeyStream only uses
+//! the letters A-J.  Every step is exactly that of a full deck, which is simply DeckState<54, 26>.
+//!
+//! # Examples
+//! ```
+//! use solitaire_cypher::deck_state::DeckState;
+//! let mut toy = DeckState::<12, 10>::new_deck();
+//! assert_eq!(toy.to_string(), "1 2 3 4 5 6 7 8 9 10 FA FB");
+//! assert_eq!(toy.key_stream(10).unwrap().to_string(), "DGFHI FCBII");
+//! ```
+
+use crate::{value_into_letter, CypherError, KeyStream, LetterValue, UpperLetter};
+use card_play::{Card, Cards, JokersPerDeck};
+use std::fmt;
+use std::fmt::Display;
+
+/// Smallest deck: a single plain card and the two jokers
+pub const MIN_DECK_SIZE: usize = 3;
+/// Largest deck whose card values fit a u8
+pub const MAX_DECK_SIZE: usize = 255;
+
+/// A complete Solitaire deck of N cards, with KeyStream letters modulo M, held as an array of
+/// card values (see module documentation)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeckState<const N: usize = 54, const M: u8 = 26>([u8; N]);
+
+impl DeckState {
+    /// Create a DeckState from Cards.  None unless the Cards are a single complete deck with
+    /// both jokers.
+    ///
+    /// # Examples
+    /// ```
+    /// use card_play::{Cards, JokersPerDeck};
+    /// use solitaire_cypher::deck_state::DeckState;
+    /// let deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+    /// let state = DeckState::from_cards(&deck).unwrap();
+    /// assert_eq!(state.to_cards(), deck);
+    /// assert!(DeckState::from_cards(&Cards::new(1, JokersPerDeck::new(1).unwrap())).is_none());
+    /// ```
+    pub fn from_cards(cards: &Cards) -> Option<DeckState> {
+        let values: [u8; 54] = cards
+            .0
+            .iter()
+            .map(|card| u8::from(card.default_value()))
+            .collect::<Vec<u8>>()
+            .try_into()
+            .ok()?;
+        DeckState::from_values(values)
+    }
+
+    /// The deck as Cards
+    pub fn to_cards(&self) -> Cards {
+        // can panic if code broken - next line uses illegal joker count
+        let new_deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+        Cards(
+            self.0
+                .iter()
+                .map(|v| new_deck.0[usize::from(*v) - 1])
+                .collect::<Vec<Card>>(),
+        )
+    }
+}
+
+impl<const N: usize, const M: u8> DeckState<N, M> {
+    /// Create a DeckState from card values.  None unless the values are each of 1..=N once, N is
+    /// from MIN_DECK_SIZE to MAX_DECK_SIZE and M is from 1 to 26.
+    ///
+    /// # Examples
+    /// ```
+    /// use solitaire_cypher::deck_state::DeckState;
+    /// assert!(DeckState::<5, 3>::from_values([3, 1, 5, 2, 4]).is_some());
+    /// assert!(DeckState::<5, 3>::from_values([3, 1, 5, 2, 2]).is_none());
+    /// assert!(DeckState::<5, 27>::from_values([3, 1, 5, 2, 4]).is_none());
+    /// ```
+    pub fn from_values(values: [u8; N]) -> Option<DeckState<N, M>> {
+        if !valid_variant(N, M) {
+            return None;
+        }
+        let mut seen = [false; N];
+        for value in values {
+            let index = usize::from(value).wrapping_sub(1);
+            if index >= N || seen[index] {
+                return None;
+            }
+            seen[index] = true;
+        }
+        Some(DeckState(values))
+    }
+
+    /// The deck in new deck order, 1..=N, with the jokers at the bottom
+    ///
+    /// Will panic if N or M is out of range (see from_values())
+    ///
+    /// # Examples
+    /// ```
+    /// use card_play::{Cards, JokersPerDeck};
+    /// use solitaire_cypher::deck_state::DeckState;
+    /// let deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+    /// assert_eq!(DeckState::<54, 26>::new_deck().to_cards(), deck);
+    /// ```
+    pub fn new_deck() -> DeckState<N, M> {
+        let mut values = [0u8; N];
+        values
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, v)| *v = (i + 1) as u8);
+        match DeckState::from_values(values) {
+            Some(state) => state,
+            None => panic!("no deck of {} cards with modulus {}", N, M),
+        }
+    }
+
+    /// The card values from the top of the deck
+    pub fn values(&self) -> &[u8; N] {
+        &self.0
+    }
+
+    /// Perform the deck changing steps of the Solitaire algorithm in place (see
+    /// next_deck_state())
+    ///
+    /// # Examples
+    /// ```
+    /// use card_play::{Cards, JokersPerDeck};
+    /// use solitaire_cypher::next_deck_state;
+    /// use solitaire_cypher::deck_state::DeckState;
+    /// let deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+    /// let mut state = DeckState::from_cards(&deck).unwrap();
+    /// state.step();
+    /// assert_eq!(state.to_cards(), next_deck_state(deck));
+    /// ```
+    pub fn step(&mut self) {
+        let deck = &mut self.0;
+        let (joker_a, joker_b) = jokers(deck);
+        let a = position(deck, joker_a);
+        move_circular(deck, a, 1);
+        let b = position(deck, joker_b);
+        move_circular(deck, b, 2);
+
+        // Triple cut swapping the cards above the first joker with those below the second
+        let a = position(deck, joker_a);
+        let b = position(deck, joker_b);
+        let (first, second) = if a < b { (a, b) } else { (b, a) };
+        deck.rotate_left(second + 1);
+        deck[N - second - 1..].rotate_left(first);
+
+        // Count cut at the value of the bottom card leaving the bottom card at the bottom
+        let count = usize::from(solitaire_value(deck, deck[N - 1]));
+        deck[..N - 1].rotate_left(count);
+    }
+
+    /// Perform one complete step of the Solitaire algorithm returning the KeyStream letter, or
+    /// None if the output card is a joker
+    ///
+    /// # Examples
+    /// ```
+    /// use card_play::{Cards, JokersPerDeck};
+    /// use solitaire_cypher::deck_state::DeckState;
+    /// let deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+    /// let mut state = DeckState::from_cards(&deck).unwrap();
+    /// let letters: Vec<Option<char>> =
+    ///     (0..5).map(|_| state.next_output().map(|l| u8::from(l) as char)).collect();
+    /// assert_eq!(letters, vec![Some('D'), Some('W'), Some('J'), None, Some('X')]);
+    /// ```
+    pub fn next_output(&mut self) -> Option<UpperLetter> {
+        self.step();
+        let deck = &self.0;
+        let output = deck[usize::from(solitaire_value(deck, deck[0]))];
+        if output >= jokers(deck).0 {
+            return None;
+        }
+        // can panic if code broken - M is at most 26 so always gives a legal letter value
+        Some(value_into_letter(
+            &LetterValue::new((output - 1) % M + 1).unwrap(),
+        ))
+    }
+
+    /// Produce a KeyStream of exactly the specified length (no rounding up to a multiple of 5)
+    /// continuing from where the last letter left off (see KeystreamGenerator::key_stream())
+    ///
+    /// returns Err CypherError::KeyStreamTooShort, with the number of letters produced, should the
+    /// deck output nothing but jokers for more steps than it has cards.  This bounds the search
+    /// for the next letter rather than reporting a known failure: no arrangement of up to 8 cards
+    /// does so (checked exhaustively) but larger decks, including the full deck, are too many to
+    /// check and it isn't ruled out for them.
+    ///
+    /// # Examples
+    /// ```
+    /// use card_play::{Cards, JokersPerDeck};
+    /// use solitaire_cypher::get_key_stream;
+    /// use solitaire_cypher::deck_state::DeckState;
+    /// let deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+    /// let mut state = DeckState::from_cards(&deck).unwrap();
+    /// assert_eq!(state.key_stream(3).unwrap().to_string(), "DWJ");
+    /// assert_eq!(state.key_stream(7).unwrap().to_string(), "XHYRF DG");
+    /// let mut state = DeckState::from_cards(&deck).unwrap();
+    /// assert_eq!(state.key_stream(100).unwrap().0, get_key_stream(deck, 100).0);
+    /// ```
+    pub fn key_stream(&mut self, len: usize) -> Result<KeyStream, CypherError> {
+        let mut key_stream = KeyStream(Vec::with_capacity(len));
+        let mut jokers_in_a_row = 0;
+        while key_stream.0.len() < len {
+            match self.next_output() {
+                Some(letter) => {
+                    key_stream.0.push(letter);
+                    jokers_in_a_row = 0;
+                }
+                None if jokers_in_a_row < N => jokers_in_a_row += 1,
+                None => {
+                    return Err(CypherError::KeyStreamTooShort {
+                        needed: len,
+                        available: key_stream.0.len(),
+                    })
+                }
+            }
+        }
+        Ok(key_stream)
+    }
+}
+
+impl<const N: usize, const M: u8> Display for DeckState<N, M> {
+    /// Card names for a full deck, otherwise the values with the jokers as FA and FB
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if N == 54 {
+            // can panic if code broken - values are always a complete deck
+            let deck = DeckState::from_values(self.0[..].try_into().unwrap()).unwrap();
+            return write!(f, "{}", deck.to_cards());
+        }
+        let (joker_a, joker_b) = jokers(&self.0);
+        let names: Vec<String> = self
+            .0
+            .iter()
+            .map(|v| match *v {
+                v if v == joker_a => "FA".to_string(),
+                v if v == joker_b => "FB".to_string(),
+                v => v.to_string(),
+            })
+            .collect();
+        write!(f, "{}", names.join(" "))
+    }
+}
+
+// Whether a deck of size cards with the modulus can be stepped and give letters
+pub(crate) fn valid_variant(size: usize, modulus: u8) -> bool {
+    (MIN_DECK_SIZE..=MAX_DECK_SIZE).contains(&size) && (1..=26).contains(&modulus)
+}
+
+fn jokers(deck: &[u8]) -> (u8, u8) {
+    let joker_b = deck.len() as u8;
+    (joker_b - 1, joker_b)
+}
+
+// Both jokers have the Solitaire value of Joker A
+fn solitaire_value(deck: &[u8], card: u8) -> u8 {
+    card.min(jokers(deck).0)
+}
+
+fn position(deck: &[u8], card: u8) -> usize {
+    // can panic if code broken - DeckState always holds every card
+    deck.iter().position(|c| *c == card).unwrap()
+}
+
+// Move the card at start down by distance treating the deck as a circle (see
+// Cards::move_card_circular())
+pub(crate) fn move_circular<T>(deck: &mut [T], start: usize, distance: usize) {
+    let mut end = (start + distance) % deck.len();
+    if end < start {
+        end += 1;
+    }
+    if end > start {
+        (start..end).for_each(|i| deck.swap(i, i + 1));
+    } else {
+        (end..start).rev().for_each(|i| deck.swap(i, i + 1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        get_key_stream, key_deck_from_passphrase, next_deck_state, KeystreamGenerator, Passphrase,
+    };
+    use std::str::FromStr;
+
+    #[test]
+    fn test_deck_state_matches_cards() {
+        const ITER_COUNT: usize = 200;
+        for _ in 0..ITER_COUNT {
+            let mut deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+            deck.shuffle_fy();
+            let mut state = DeckState::from_cards(&deck).unwrap();
+            let mut generator = KeystreamGenerator::new(deck.clone());
+            for _ in 0..20 {
+                let output = generator.next_output();
+                assert_eq!(state.next_output(), output.letter);
+                assert_eq!(state.to_cards(), *generator.deck());
+            }
+            let mut state = DeckState::from_cards(&deck).unwrap();
+            state.step();
+            assert_eq!(state.to_cards(), next_deck_state(deck));
+        }
+    }
+
+    #[test]
+    fn test_key_stream_matches_get_key_stream() {
+        const ITER_COUNT: usize = 100;
+        for i in 0..ITER_COUNT {
+            let mut deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+            deck.shuffle_fy();
+            let len = i * 5;
+            let mut state = DeckState::from_cards(&deck).unwrap();
+            assert_eq!(
+                state.key_stream(len).unwrap().0,
+                get_key_stream(deck.clone(), len).0
+            );
+            // continues exactly where a KeystreamGenerator would
+            let mut generator = KeystreamGenerator::new(deck);
+            generator.key_stream(len);
+            assert_eq!(state.key_stream(7).unwrap().0, generator.key_stream(7).0);
+            assert_eq!(state.to_cards(), *generator.deck());
+        }
+        for passphrase in ["cryptonomicon", "foo", "solitaire"] {
+            let deck = key_deck_from_passphrase(&Passphrase::from_str(passphrase).unwrap());
+            let mut state = DeckState::from_cards(&deck).unwrap();
+            assert_eq!(
+                state.key_stream(1000).unwrap().0,
+                get_key_stream(deck, 1000).0
+            );
+        }
+    }
+
+    #[test]
+    fn test_key_stream_never_short() {
+        // every arrangement gives a letter within N steps, so any length of KeyStream
+        fn arrange<const N: usize>(values: &mut [u8; N], k: usize) {
+            if k == N {
+                let mut state = DeckState::<N, 26>::from_values(*values).unwrap();
+                assert_eq!(state.key_stream(1).unwrap().len(), 1, "{:?}", values);
+                return;
+            }
+            for i in k..N {
+                values.swap(k, i);
+                arrange(values, k + 1);
+                values.swap(k, i);
+            }
+        }
+        arrange(&mut DeckState::<3, 26>::new_deck().0, 0);
+        arrange(&mut DeckState::<4, 26>::new_deck().0, 0);
+        arrange(&mut DeckState::<5, 26>::new_deck().0, 0);
+        arrange(&mut DeckState::<6, 26>::new_deck().0, 0);
+        arrange(&mut DeckState::<7, 26>::new_deck().0, 0);
+        arrange(&mut DeckState::<8, 26>::new_deck().0, 0);
+    }
+
+    #[test]
+    fn test_reduced_decks() {
+        // the smallest deck steps and gives letters, all A as its only plain card is 1
+        let mut smallest = DeckState::<3, 26>::new_deck();
+        let ks = smallest.key_stream(10).unwrap();
+        assert_eq!(ks.len(), 10);
+        assert!(ks.0.iter().all(|l| u8::from(*l) == b'A'));
+        // the modulus limits the letters used
+        let mut toy = DeckState::<28, 7>::from_values(
+            (1..=28u8).rev().collect::<Vec<u8>>().try_into().unwrap(),
+        )
+        .unwrap();
+        let ks = toy.key_stream(500).unwrap();
+        assert_eq!(ks.len(), 500);
+        assert!(ks.0.iter().all(|l| u8::from(*l) <= b'G'));
+        assert!(ks.0.iter().any(|l| u8::from(*l) == b'G'));
+        // sizes and moduli out of range
+        assert!(DeckState::<2, 26>::from_values([1, 2]).is_none());
+        assert!(DeckState::<3, 0>::from_values([1, 2, 3]).is_none());
+        assert_eq!(DeckState::<5, 5>::new_deck().to_string(), "1 2 3 FA FB");
+    }
+}
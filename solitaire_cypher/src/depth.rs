@@ -0,0 +1,229 @@
+//! # Depth
+//!
+//! Detection of messages "in depth", i.e. encrypted with the same KeyStream (e.g. the same
+//! passphrase without a message indicator).  Subtracting one such CypherText from the other
+//! cancels the KeyStream out, leaving the difference of the two PlainTexts, which is usually
+//! enough to recover both.
+//!
+//! The letter by letter differences of the aligned CypherTexts are measured.  For unrelated
+//! KeyStreams the differences are uniformly random so the same letter appears in both (a
+//! difference of zero, a coincidence) 1 time in 26 (kappa 0.0385) and the index of coincidence of
+//! the differences is 1.0.  For messages in depth the differences are those of the PlainTexts so,
+//! for English, the same letter appears in both about 1 time in 15 (kappa 0.066) and the index of
+//! coincidence of the differences is above 1.0.  Pairs are flagged on the z-score of the index
+//! of coincidence of the differences, i.e. how many standard deviations it is above the 1.0 of
+//! unrelated KeyStreams.  The signal is weak so length matters: in simulated depths of English
+//! prose around 300 aligned letters are flagged only about 1 time in 5 at DEFAULT_THRESHOLD and
+//! 1,200 to 1,400 are needed to flag 9 times in 10.  Unrelated pairs are flagged about 1 time in
+//! 60 at DEFAULT_THRESHOLD (1 in 150 at 3.0), which adds up over the many pairs of a large set of
+//! messages.
+//!
+//! Only depth aligned from the first letters is detected: CypherTexts starting with a message
+//! indicator should have it removed first, and messages whose KeyStreams overlap at an offset are
+//! not found.
+
+use crate::{letter_into_value, CypherText};
+use std::fmt;
+use std::fmt::Display;
+
+const LETTERS: usize = 26;
+
+/// z-score of the difference index of coincidence at or above which a pair is flagged as likely
+/// in depth (see module documentation for what it detects and how often it is wrong)
+pub const DEFAULT_THRESHOLD: f64 = 2.5;
+
+/// Measurements of a pair of CypherTexts aligned from their first letters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PairDepth {
+    /// Index of the first CypherText of the pair
+    pub first: usize,
+    /// Index of the second CypherText of the pair
+    pub second: usize,
+    /// Number of aligned letters (the length of the shorter CypherText)
+    pub overlap: usize,
+    /// Number of aligned positions with the same letter in both (a difference of zero)
+    pub coincidences: usize,
+    /// Fraction of aligned positions with the same letter in both (1/26 expected of unrelated
+    /// KeyStreams)
+    pub kappa: f64,
+    /// Index of coincidence of the differences, normalized so that 1.0 is expected of unrelated
+    /// KeyStreams
+    pub difference_ic: f64,
+    /// Number of standard deviations the difference index of coincidence is above the 1.0
+    /// expected of unrelated KeyStreams
+    pub z_score: f64,
+}
+
+impl PairDepth {
+    /// Measure a pair of CypherTexts, identified by the given indices, aligned from their first
+    /// letters
+    ///
+    /// # Examples
+    /// ```
+    /// use std::str::FromStr;
+    /// use solitaire_cypher::CypherText;
+    /// use solitaire_cypher::depth::PairDepth;
+    /// let a = CypherText::from_str("ABCDE FGHIJ").unwrap();
+    /// let b = CypherText::from_str("ABCDE ZZZZZ ZZ").unwrap();
+    /// let pair = PairDepth::measure(0, &a, 1, &b);
+    /// assert_eq!((pair.overlap, pair.coincidences), (10, 5));
+    /// assert_eq!(pair.kappa, 0.5);
+    /// ```
+    pub fn measure(first: usize, a: &CypherText, second: usize, b: &CypherText) -> PairDepth {
+        let mut counts = [0usize; LETTERS];
+        for (x, y) in a.0.iter().zip(b.0.iter()) {
+            let x = usize::from(u8::from(letter_into_value(x)));
+            let y = usize::from(u8::from(letter_into_value(y)));
+            counts[(x + LETTERS - y) % LETTERS] += 1;
+        }
+        let overlap = a.len().min(b.len());
+        let coincidences = counts[0];
+
+        let n = overlap as f64;
+        let kappa = if overlap == 0 {
+            0.0
+        } else {
+            coincidences as f64 / n
+        };
+        let (difference_ic, z_score) = if overlap < 2 {
+            (0.0, 0.0)
+        } else {
+            let pairs: usize = counts.iter().map(|c| c * c.saturating_sub(1)).sum();
+            let difference_ic = LETTERS as f64 * pairs as f64 / (n * (n - 1.0));
+            // the variance of the (normalized) index of coincidence of n uniformly random letters
+            let variance = 2.0 * (LETTERS - 1) as f64 / (n * (n - 1.0));
+            (difference_ic, (difference_ic - 1.0) / variance.sqrt())
+        };
+        PairDepth {
+            first,
+            second,
+            overlap,
+            coincidences,
+            kappa,
+            difference_ic,
+            z_score,
+        }
+    }
+
+    /// Whether the pair is likely in depth, i.e. the z-score is at least threshold (see
+    /// DEFAULT_THRESHOLD)
+    pub fn in_depth(&self, threshold: f64) -> bool {
+        self.z_score >= threshold
+    }
+}
+
+impl Display for PairDepth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "overlap {:<5} coincidences {:<4} kappa {:.4}  difference IC {:.3}  z-score {:.1}",
+            self.overlap, self.coincidences, self.kappa, self.difference_ic, self.z_score
+        )
+    }
+}
+
+/// Measure every pair of the CypherTexts (see PairDepth::measure()), most likely in depth first
+///
+/// # Examples
+/// ```
+/// use std::str::FromStr;
+/// use solitaire_cypher::{encrypt, get_key_stream, key_deck_from_passphrase, Passphrase, PlainText};
+/// use solitaire_cypher::depth::{measure_depths, DEFAULT_THRESHOLD};
+/// let text = "it was the best of times it was the worst of times it was the age of wisdom \
+///             it was the age of foolishness it was the epoch of belief it was the epoch of \
+///             incredulity it was the season of light it was the season of darkness it was the \
+///             spring of hope it was the winter of despair we had everything before us we had \
+///             nothing before us we were all going direct to heaven we were all going direct \
+///             the other way";
+/// let (first, second) = text.split_at(text.len() / 2);
+/// let encrypt_with = |text: &str, passphrase: &str| {
+///     let pt = PlainText::from_str(text).unwrap();
+///     let deck = key_deck_from_passphrase(&Passphrase::from_str(passphrase).unwrap());
+///     encrypt(&pt, &get_key_stream(deck, pt.len()))
+/// };
+/// let cts = vec![
+///     encrypt_with(first, "dickens"),
+///     encrypt_with(second, "twocities"),
+///     encrypt_with(second, "dickens"),
+/// ];
+/// let depths = measure_depths(&cts);
+/// assert_eq!(depths.len(), 3);
+/// assert_eq!((depths[0].first, depths[0].second), (0, 2));
+/// assert!(depths[0].in_depth(DEFAULT_THRESHOLD));
+/// assert!(!depths[1].in_depth(DEFAULT_THRESHOLD));
+/// ```
+pub fn measure_depths(cts: &[CypherText]) -> Vec<PairDepth> {
+    let mut depths = Vec::with_capacity(cts.len() * cts.len().saturating_sub(1) / 2);
+    for (i, a) in cts.iter().enumerate() {
+        for (j, b) in cts.iter().enumerate().skip(i + 1) {
+            depths.push(PairDepth::measure(i, a, j, b));
+        }
+    }
+    depths.sort_by(|x, y| y.z_score.total_cmp(&x.z_score));
+    depths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{encrypt, get_key_stream, key_deck_from_passphrase, Passphrase, PlainText};
+    use std::str::FromStr;
+
+    const GETTYSBURG: &str = "Four score and seven years ago our fathers brought forth on this \
+        continent, a new nation, conceived in Liberty, and dedicated to the proposition that all \
+        men are created equal. Now we are engaged in a great civil war, testing whether that \
+        nation, or any nation so conceived and so dedicated, can long endure. We are met on a \
+        great battle-field of that war. We have come to dedicate a portion of that field, as a \
+        final resting place for those who here gave their lives that that nation might live. It \
+        is altogether fitting and proper that we should do this. But, in a larger sense, we can \
+        not dedicate -- we can not consecrate -- we can not hallow -- this ground. The brave \
+        men, living and dead, who struggled here, have consecrated it, far above our poor power \
+        to add or detract. The world will little note, nor long remember what we say here, but \
+        it can never forget what they did here. It is for us the living, rather, to be \
+        dedicated here to the unfinished work which they who fought here have thus far so nobly \
+        advanced. It is rather for us to be here dedicated to the great task remaining before \
+        us -- that from these honored dead we take increased devotion to that cause for which \
+        they gave the last full measure of devotion -- that we here highly resolve that these \
+        dead shall not have died in vain -- that this nation, under God, shall have a new birth \
+        of freedom -- and that government of the people, by the people, for the people, shall \
+        not perish from the earth.";
+
+    fn encrypt_with(text: &str, passphrase: &str) -> CypherText {
+        let pt = PlainText::from_str(text).unwrap();
+        let deck = key_deck_from_passphrase(&Passphrase::from_str(passphrase).unwrap());
+        encrypt(&pt, &get_key_stream(deck, pt.len()))
+    }
+
+    #[test]
+    fn test_depth_detected() {
+        let (first, second) = GETTYSBURG.split_at(GETTYSBURG.len() / 2);
+        let cts = vec![
+            encrypt_with(first, "lincoln"),
+            encrypt_with(second, "gettysburg"),
+            encrypt_with(second, "lincoln"),
+            encrypt_with(first, "address"),
+        ];
+        let depths = measure_depths(&cts);
+        assert_eq!(depths.len(), 6);
+        assert_eq!((depths[0].first, depths[0].second), (0, 2));
+        assert!(depths[0].in_depth(DEFAULT_THRESHOLD));
+        assert!(depths[0].kappa > 0.055);
+        assert!(depths[0].difference_ic > 1.0);
+        assert!(depths[1..].iter().all(|d| !d.in_depth(DEFAULT_THRESHOLD)));
+    }
+
+    #[test]
+    fn test_measure_edge_cases() {
+        let empty = CypherText::new();
+        let pair = PairDepth::measure(0, &empty, 1, &empty);
+        assert_eq!((pair.overlap, pair.kappa, pair.z_score), (0, 0.0, 0.0));
+        let a = CypherText::from_str("AZ").unwrap();
+        let b = CypherText::from_str("BA").unwrap();
+        // both differences are 25 (Z)
+        let pair = PairDepth::measure(0, &a, 1, &b);
+        assert_eq!(pair.coincidences, 0);
+        assert_eq!(pair.difference_ic, 26.0);
+        // 25 standard deviations of sqrt(2 * 25 / 2) above 1.0
+        assert_eq!(pair.z_score, 5.0);
+    }
+}
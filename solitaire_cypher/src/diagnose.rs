@@ -0,0 +1,411 @@
+//! # Diagnose
+//!
+//! Find where a hand computed KeyStream (or CypherText) went wrong.  Doing Solitaire with a real
+//! deck, a single slip (a joker moved one card too far, a miscounted cut...) scrambles every
+//! letter that follows.  Given the correct key deck and the letters a person produced, the first
+//! diverging letter is found and the deck states near it are searched for the single mistake
+//! which best explains the letters from there on.
+
+use crate::{
+    count_cut, output_card, output_card_at, triple_cut, try_decrypt, CypherText, KeyStream,
+    KeystreamGenerator, OutputCard, PlainText, UpperLetter, Value,
+};
+use card_play::{Card, Cards, JokerId};
+use std::fmt;
+use std::fmt::Display;
+
+// How many letters before the first diverging letter to look for the mistake - a mistake can
+// occasionally leave a letter or two unchanged
+const LOOK_BACK: usize = 2;
+
+/// A single mistake made while performing one step of the Solitaire algorithm by hand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mistake {
+    /// Joker A moved the given number of cards instead of one
+    JokerAMoved(isize),
+    /// Joker B moved the given number of cards instead of two
+    JokerBMoved(isize),
+    /// Joker A wrapped from the bottom to the top counting the wrap as a move
+    JokerAWrapCounted,
+    /// Joker B wrapped from the bottom to the top counting the wrap as a move
+    JokerBWrapCounted,
+    /// The triple cut was not done
+    TripleCutSkipped,
+    /// The triple cut moved the jokers along with the cards above and below them
+    TripleCutJokersMoved,
+    /// Only the cards above the first joker were moved (to the bottom)
+    TripleCutTopOnly,
+    /// Only the cards below the second joker were moved (to the top)
+    TripleCutBottomOnly,
+    /// The count cut took the given number of cards more (or fewer if negative) than the value
+    /// of the bottom card
+    CountCutMiscounted(isize),
+    /// The count cut was not done
+    CountCutSkipped,
+    /// The count cut moved the bottom card rather than leaving it at the bottom
+    CountCutBottomCardMoved,
+    /// The output card was counted down the given number of cards too far (or short if
+    /// negative).  The deck is unaffected so only one letter is wrong.
+    OutputMiscounted(isize),
+    /// A joker output card was used as a letter rather than skipped
+    JokerNotSkipped,
+}
+
+impl Mistake {
+    // The mistakes searched for at each step, roughly most likely first
+    fn all() -> Vec<Mistake> {
+        let mut mistakes = vec![
+            Mistake::JokerAMoved(2),
+            Mistake::JokerAMoved(0),
+            Mistake::JokerBMoved(1),
+            Mistake::JokerBMoved(3),
+            Mistake::JokerBMoved(0),
+            Mistake::JokerAWrapCounted,
+            Mistake::JokerBWrapCounted,
+            Mistake::TripleCutSkipped,
+            Mistake::TripleCutJokersMoved,
+            Mistake::TripleCutTopOnly,
+            Mistake::TripleCutBottomOnly,
+        ];
+        for delta in [1, -1, 2, -2] {
+            mistakes.push(Mistake::CountCutMiscounted(delta));
+        }
+        mistakes.push(Mistake::CountCutSkipped);
+        mistakes.push(Mistake::CountCutBottomCardMoved);
+        for delta in [1, -1, 2, -2] {
+            mistakes.push(Mistake::OutputMiscounted(delta));
+        }
+        mistakes.push(Mistake::JokerNotSkipped);
+        mistakes
+    }
+}
+
+impl Display for Mistake {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mistake::JokerAMoved(n) => write!(f, "Joker A moved {} card(s) instead of 1", n),
+            Mistake::JokerBMoved(n) => write!(f, "Joker B moved {} card(s) instead of 2", n),
+            Mistake::JokerAWrapCounted => {
+                write!(f, "Joker A wrap from bottom to top counted as a move")
+            }
+            Mistake::JokerBWrapCounted => {
+                write!(f, "Joker B wrap from bottom to top counted as a move")
+            }
+            Mistake::TripleCutSkipped => write!(f, "triple cut skipped"),
+            Mistake::TripleCutJokersMoved => {
+                write!(f, "triple cut moved the jokers with the outer stacks")
+            }
+            Mistake::TripleCutTopOnly => {
+                write!(f, "triple cut only moved the cards above the first joker")
+            }
+            Mistake::TripleCutBottomOnly => {
+                write!(f, "triple cut only moved the cards below the second joker")
+            }
+            Mistake::CountCutMiscounted(n) => {
+                write!(f, "count cut miscounted by {:+} card(s)", n)
+            }
+            Mistake::CountCutSkipped => write!(f, "count cut skipped"),
+            Mistake::CountCutBottomCardMoved => {
+                write!(f, "count cut did not leave the bottom card at the bottom")
+            }
+            Mistake::OutputMiscounted(n) => {
+                write!(f, "output card miscounted by {:+} card(s)", n)
+            }
+            Mistake::JokerNotSkipped => write!(f, "joker output card not skipped"),
+        }
+    }
+}
+
+/// A mistake which could explain the letters produced and how well it does so
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candidate {
+    /// 1 based Solitaire step (including steps which output a joker) in which the mistake was made
+    pub step: usize,
+    /// The mistake
+    pub mistake: Mistake,
+    /// Number of letters, from the first diverging letter on, that the mistake reproduces
+    pub matched: usize,
+}
+
+impl Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "step {}: {} (explains {} letter(s))",
+            self.step, self.mistake, self.matched
+        )
+    }
+}
+
+/// Result of comparing hand computed letters with the correct ones
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnosis {
+    /// Zero based index of the first letter which differs from the correct KeyStream
+    pub first_divergence: usize,
+    /// Number of letters compared from the first diverging letter on
+    pub compared: usize,
+    /// Mistakes which reproduce the diverging letter, best explanation first
+    pub candidates: Vec<Candidate>,
+}
+
+impl Diagnosis {
+    /// The most likely mistake, if any single mistake explains the diverging letter
+    pub fn likely(&self) -> Option<&Candidate> {
+        self.candidates.first()
+    }
+}
+
+impl Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "first wrong letter: {}", self.first_divergence + 1)?;
+        match self.likely() {
+            Some(candidate) => write!(f, ", likely mistake at {}", candidate),
+            None => write!(f, ", no single mistake explains it"),
+        }
+    }
+}
+
+/// Compare a hand computed KeyStream with that generated from the correct key deck.  Returns
+/// None if the letters all match, otherwise the first diverging letter and the single mistakes
+/// (best first) which reproduce the hand computed letters from there on.
+///
+/// # Examples
+/// ```
+/// use std::str::FromStr;
+/// use card_play::{Cards, JokersPerDeck};
+/// use solitaire_cypher::KeyStream;
+/// use solitaire_cypher::diagnose::{diagnose_key_stream, Mistake};
+/// let deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+/// // correct is DWJXH YRFDG, but the count cut of the 7th step was one card short
+/// let human = KeyStream::from_str("DWJXH XTHGM").unwrap();
+/// let diagnosis = diagnose_key_stream(&deck, &human).unwrap();
+/// assert_eq!(diagnosis.first_divergence, 5);
+/// let likely = diagnosis.likely().unwrap();
+/// assert_eq!((likely.step, likely.mistake), (7, Mistake::CountCutMiscounted(-1)));
+/// ```
+pub fn diagnose_key_stream(key_deck: &Cards, human: &KeyStream) -> Option<Diagnosis> {
+    // Generate the correct letters recording the deck before, and letter count before, each step
+    let mut generator = KeystreamGenerator::new(key_deck.clone());
+    let mut correct: Vec<UpperLetter> = Vec::new();
+    let mut steps: Vec<(Cards, usize)> = Vec::new();
+    while correct.len() < human.len() {
+        steps.push((generator.deck().clone(), correct.len()));
+        if let Some(letter) = generator.next_output().letter {
+            correct.push(letter);
+        }
+    }
+    let first = correct
+        .iter()
+        .zip(human.0.iter())
+        .position(|(c, h)| c != h)?;
+    let compared = human.len() - first;
+
+    let mut candidates = Vec::new();
+    for (i, (deck, letters_before)) in steps.iter().enumerate() {
+        if *letters_before > first || *letters_before + LOOK_BACK < first {
+            continue;
+        }
+        for mistake in Mistake::all() {
+            let Some(letters) = letters_with_mistake(deck, mistake, human.len() - letters_before)
+            else {
+                continue;
+            };
+            // letters up to the first divergence must still be correct
+            let (agree, rest) = letters.split_at(first - letters_before);
+            if agree != &human.0[*letters_before..first] || rest[0] != human.0[first] {
+                continue;
+            }
+            let matched = rest
+                .iter()
+                .zip(human.0[first..].iter())
+                .filter(|(m, h)| m == h)
+                .count();
+            candidates.push(Candidate {
+                step: i + 1,
+                mistake,
+                matched,
+            });
+        }
+    }
+    // best explanation first, favouring the mistake made closest to the first diverging letter
+    candidates.sort_by(|a, b| b.matched.cmp(&a.matched).then(b.step.cmp(&a.step)));
+
+    Some(Diagnosis {
+        first_divergence: first,
+        compared,
+        candidates,
+    })
+}
+
+/// Compare a hand computed CypherText of a known PlainText with the correct encryption using the
+/// key deck (see diagnose_key_stream()).  The hand computed KeyStream is recovered by
+/// subtracting the PlainText from the CypherText.
+///
+/// # Examples
+/// ```
+/// use std::str::FromStr;
+/// use solitaire_cypher::{key_deck_from_passphrase, CypherText, Passphrase, PlainText};
+/// use solitaire_cypher::diagnose::diagnose_cypher_text;
+/// let deck = key_deck_from_passphrase(&Passphrase::from_str("cryptonomicon").unwrap());
+/// let pt = PlainText::from_str("SOLITAIRE").unwrap();
+/// assert!(diagnose_cypher_text(&deck, &pt, &CypherText::from_str("KIRAK SFJAN").unwrap()).is_none());
+/// let diagnosis = diagnose_cypher_text(&deck, &pt, &CypherText::from_str("KIRAK SFJAM").unwrap());
+/// assert_eq!(diagnosis.unwrap().first_divergence, 9);
+/// ```
+pub fn diagnose_cypher_text(
+    key_deck: &Cards,
+    pt: &PlainText,
+    human_ct: &CypherText,
+) -> Option<Diagnosis> {
+    let len = pt.len().min(human_ct.len());
+    let ct = CypherText(human_ct.0[..len].to_vec());
+    // CypherText minus PlainText is the KeyStream - and lengths match so this can't fail
+    let human = try_decrypt(&ct, &KeyStream(pt.0[..len].to_vec())).ok()?;
+    diagnose_key_stream(key_deck, &KeyStream(human.0))
+}
+
+// Letters produced, starting with the step in which the mistake is made and continuing correctly
+// thereafter.  None if the mistake isn't possible from this deck (i.e. it would make no
+// difference or would count beyond the deck)
+fn letters_with_mistake(deck: &Cards, mistake: Mistake, len: usize) -> Option<Vec<UpperLetter>> {
+    let (deck, output) = step_with_mistake(deck.clone(), mistake)?;
+    let mut letters = Vec::new();
+    match (mistake, output.letter) {
+        (Mistake::JokerNotSkipped, None) => {
+            letters.push(UpperLetter::new(b'A' + (u8::from(output.value) - 1) % 26).unwrap())
+        }
+        (Mistake::JokerNotSkipped, Some(_)) => return None,
+        (_, Some(letter)) => letters.push(letter),
+        (_, None) => {}
+    }
+    let generator = KeystreamGenerator::new(deck);
+    letters.extend(generator.take(len.saturating_sub(letters.len())));
+    letters.truncate(len);
+    Some(letters)
+}
+
+// One Solitaire step with the given mistake, returning the resulting deck and output card
+fn step_with_mistake(mut deck: Cards, mistake: Mistake) -> Option<(Cards, OutputCard)> {
+    let joker_a = Card::Joker(JokerId::A);
+    let joker_b = Card::Joker(JokerId::B);
+    let wraps = |deck: &Cards, card: Card, distance: usize| -> bool {
+        deck.find(card).unwrap() + distance >= deck.len()
+    };
+
+    match mistake {
+        Mistake::JokerAMoved(n) => assert!(deck.move_card_circular(joker_a, 0, n)),
+        Mistake::JokerAWrapCounted if wraps(&deck, joker_a, 1) => {
+            assert!(deck.move_card(joker_a, 0, 1))
+        }
+        Mistake::JokerAWrapCounted => return None,
+        _ => assert!(deck.move_card_circular(joker_a, 0, 1)),
+    }
+    match mistake {
+        Mistake::JokerBMoved(n) => assert!(deck.move_card_circular(joker_b, 0, n)),
+        Mistake::JokerBWrapCounted if wraps(&deck, joker_b, 2) => {
+            assert!(deck.move_card(joker_b, 0, 2))
+        }
+        Mistake::JokerBWrapCounted => return None,
+        _ => assert!(deck.move_card_circular(joker_b, 0, 2)),
+    }
+
+    // positions of the first and second jokers from the top
+    let first = deck.0.iter().position(|c| matches!(c, Card::Joker(_)))?;
+    let second = deck.0.iter().rposition(|c| matches!(c, Card::Joker(_)))?;
+    deck = match mistake {
+        Mistake::TripleCutSkipped => deck,
+        Mistake::TripleCutJokersMoved => {
+            let mut cards = deck.0[second..].to_vec();
+            cards.extend_from_slice(&deck.0[first + 1..second]);
+            cards.extend_from_slice(&deck.0[..=first]);
+            Cards(cards)
+        }
+        Mistake::TripleCutTopOnly => {
+            let mut cards = deck.0[first..].to_vec();
+            cards.extend_from_slice(&deck.0[..first]);
+            Cards(cards)
+        }
+        Mistake::TripleCutBottomOnly => {
+            let mut cards = deck.0[second + 1..].to_vec();
+            cards.extend_from_slice(&deck.0[..=second]);
+            Cards(cards)
+        }
+        _ => triple_cut(deck),
+    };
+
+    let bottom_value = isize::from(u8::from(deck.look_at(deck.len() - 1).ok()?.value()));
+    deck = match mistake {
+        Mistake::CountCutMiscounted(n) => {
+            let count = bottom_value + n;
+            if count < 0 || count >= deck.len() as isize {
+                return None;
+            }
+            count_cut(deck, count as usize)
+        }
+        Mistake::CountCutSkipped => deck,
+        Mistake::CountCutBottomCardMoved => {
+            let mut cards = deck.0.split_off(bottom_value as usize);
+            cards.append(&mut deck.0);
+            Cards(cards)
+        }
+        _ => count_cut(deck, bottom_value as usize),
+    };
+
+    let output = match mistake {
+        Mistake::OutputMiscounted(n) => {
+            let top_value = isize::from(u8::from(deck.look_at(0).ok()?.value()));
+            let position = top_value + n;
+            if position < 0 {
+                return None;
+            }
+            output_card_at(&deck, position as usize)?
+        }
+        _ => output_card(&deck),
+    };
+    Some((deck, output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_key_stream, key_deck_from_passphrase, Passphrase};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_diagnose_each_mistake() {
+        let key_deck = key_deck_from_passphrase(&Passphrase::from_str("cryptonomicon").unwrap());
+        let correct = get_key_stream(key_deck.clone(), 40);
+        assert!(diagnose_key_stream(&key_deck, &correct).is_none());
+
+        // make each mistake at step 5 and confirm it is among the best explanations
+        let mut generator = KeystreamGenerator::new(key_deck.clone());
+        let mut letters = Vec::new();
+        for _ in 0..4 {
+            letters.push(generator.next_output().letter);
+        }
+        let prefix: Vec<UpperLetter> = letters.into_iter().flatten().collect();
+        for mistake in Mistake::all() {
+            let Some(rest) = letters_with_mistake(generator.deck(), mistake, 40 - prefix.len())
+            else {
+                continue;
+            };
+            let mut human = prefix.clone();
+            human.extend(rest);
+            let human = KeyStream(human);
+            let Some(diagnosis) = diagnose_key_stream(&key_deck, &human) else {
+                // mistake happened to make no difference
+                continue;
+            };
+            let best = diagnosis.likely().unwrap().matched;
+            assert_eq!(best, diagnosis.compared, "{} not fully explained", mistake);
+            assert!(
+                diagnosis
+                    .candidates
+                    .iter()
+                    .any(|c| c.matched == best && c.step == 5 && c.mistake == mistake),
+                "{} not found",
+                mistake
+            );
+        }
+    }
+}
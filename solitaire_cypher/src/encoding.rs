@@ -0,0 +1,163 @@
+//! # Encoding
+//!
+//! Solitaire only encrypts the letters A-Z so anything else has to be turned into letters before
+//! encryption and turned back after decryption.  Encoding::Letters is the traditional approach of
+//! dropping everything but the letters.  Encoding::Escape is a reversible scheme where an escape
+//! letter followed by a code letter stands for a digit, space or common punctuation mark, which
+//! keeps numbers, times and grid references intact.
+//!
+//! With escape letter Z the codes are:
+//!
+//! | Symbol | Code | Symbol | Code | Symbol | Code | Symbol | Code |
+//! |--------|------|--------|------|--------|------|--------|------|
+//! | 0      | ZA   | 7      | ZH   | ?      | ZN   | '      | ZT   |
+//! | 1      | ZB   | 8      | ZI   | !      | ZO   | "      | ZU   |
+//! | 2      | ZC   | 9      | ZJ   | :      | ZP   | (      | ZV   |
+//! | 3      | ZD   | space  | ZK   | ;      | ZQ   | )      | ZW   |
+//! | 4      | ZE   | .      | ZL   | -      | ZR   | +      | ZX   |
+//! | 5      | ZF   | ,      | ZM   | /      | ZS   | =      | ZY   |
+//! | 6      | ZG   |        |      |        |      | Z      | ZZ   |
+//!
+//! With any other escape letter the code letters are the remaining 25 letters in alphabetical
+//! order, e.g. with escape letter Q the space is QK and = is QZ.
+
+use crate::transliterate::transliterate_char;
+use crate::{CypherError, PlainText, UpperLetter};
+
+/// The symbols which Encoding::Escape can encode, in code letter order
+const SYMBOLS: [char; 25] = [
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', ' ', '.', ',', '?', '!', ':', ';', '-', '/',
+    '\'', '"', '(', ')', '+', '=',
+];
+
+/// Schemes for turning text into letters for encryption and back again after decryption.  Accented
+/// and other non-ASCII letters are transliterated (see transliterate::transliterate_char()) first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// Keep only the letters, everything else is lost
+    #[default]
+    Letters,
+    /// Encode digits, whitespace (as a space) and common punctuation as the escape letter followed
+    /// by a code letter, and the escape letter itself as the escape letter twice.  Anything else
+    /// is lost.  Every other letter is a code letter, so pad with Padding::Marked using a pad
+    /// letter other than the escape letter: Padding::Letter could strip a code letter.
+    Escape(UpperLetter),
+}
+
+impl Encoding {
+    /// Turn text into (unpadded, see PlainText::pad()) letters
+    ///
+    /// # Examples
+    /// ```
+    /// use solitaire_cypher::UpperLetter;
+    /// use solitaire_cypher::encoding::Encoding;
+    /// let text = "Meet at 0930";
+    /// assert_eq!(Encoding::Letters.encode(text).to_string(), "MEETAT");
+    /// let escape = Encoding::Escape(UpperLetter::new(b'Z').unwrap());
+    /// assert_eq!(escape.encode(text).to_string(), "MEETZKATZKZAZJZDZA");
+    /// ```
+    pub fn encode(&self, text: &str) -> PlainText {
+        let mut pt = PlainText::new();
+        for character in text.chars() {
+            let symbol = if character.is_whitespace() {
+                ' '
+            } else {
+                character
+            };
+            transliterate_char(symbol, |c| self.encode_ascii(c, &mut pt));
+        }
+        pt
+    }
+
+    fn encode_ascii(&self, c: char, pt: &mut PlainText) {
+        if let Some(letter) = UpperLetter::new(c.to_ascii_uppercase() as u8) {
+            pt.0.push(letter);
+            if *self == Encoding::Escape(letter) {
+                pt.0.push(letter);
+            }
+        } else if let Encoding::Escape(escape) = self {
+            if let Some(index) = SYMBOLS.iter().position(|s| *s == c) {
+                pt.0.push(*escape);
+                pt.0.push(code_letter(*escape, index));
+            }
+        }
+    }
+
+    /// Turn decrypted letters back into text
+    ///
+    /// returns Err CypherError::UnfinishedEscape if Encoding::Escape and the letters end with an
+    /// escape letter with no code letter following it
+    ///
+    /// # Examples
+    /// ```
+    /// use solitaire_cypher::{CypherError, PlainText, UpperLetter};
+    /// use solitaire_cypher::encoding::Encoding;
+    /// let escape = Encoding::Escape(UpperLetter::new(b'Z').unwrap());
+    /// let pt = PlainText::from_letters("GRIDZKNTZDZEZFZHZIZJZZ");
+    /// assert_eq!(escape.decode(&pt).unwrap(), "GRID NT345789Z");
+    /// assert_eq!(Encoding::Letters.decode(&pt).unwrap(), pt.to_string());
+    /// let pt = PlainText::from_letters("ABCZ");
+    /// assert_eq!(escape.decode(&pt), Err(CypherError::UnfinishedEscape));
+    /// ```
+    pub fn decode(&self, pt: &PlainText) -> Result<String, CypherError> {
+        let escape = match self {
+            Encoding::Letters => return Ok(pt.to_string()),
+            Encoding::Escape(escape) => *escape,
+        };
+        let mut text = String::with_capacity(pt.len());
+        let mut letters = pt.0.iter();
+        while let Some(letter) = letters.next() {
+            if *letter != escape {
+                text.push(u8::from(*letter) as char);
+                continue;
+            }
+            let code = letters.next().ok_or(CypherError::UnfinishedEscape)?;
+            if *code == escape {
+                text.push(u8::from(escape) as char);
+            } else {
+                let mut index = usize::from(u8::from(*code) - b'A');
+                if code > &escape {
+                    index -= 1;
+                }
+                text.push(SYMBOLS[index]);
+            }
+        }
+        Ok(text)
+    }
+}
+
+// The code letter of the symbol at index, skipping over the escape letter
+fn code_letter(escape: UpperLetter, index: usize) -> UpperLetter {
+    let mut letter = b'A' + index as u8;
+    if letter >= u8::from(escape) {
+        letter += 1;
+    }
+    // can panic if code broken - index is below 25 so the letter is at most Z
+    UpperLetter::new(letter).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_round_trip() {
+        let text = "GRID REF 123 456, ETA 09:30 (ZULU) - QUIZ? \"A+B=C\" 'X'/Y; GO!.";
+        for escape in [b'A', b'J', b'Q', b'X', b'Z'] {
+            let encoding = Encoding::Escape(UpperLetter::new(escape).unwrap());
+            let pt = encoding.encode(text);
+            assert_eq!(encoding.decode(&pt).unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn test_escape_encode() {
+        let q = Encoding::Escape(UpperLetter::new(b'Q').unwrap());
+        // whitespace becomes a space, lower case becomes upper and unknown symbols are lost
+        assert_eq!(q.encode("a\tq\n=*").to_string(), "AQKQQQKQZ");
+        assert_eq!(q.decode(&q.encode("a\tq\n=*")).unwrap(), "A Q =");
+        assert_eq!(q.encode("Ørsted – 5½").to_string(), "ORSTEDQKQSQKQF");
+        let a = Encoding::Escape(UpperLetter::new(b'A').unwrap());
+        assert_eq!(a.encode("0A9").to_string(), "ABAAAK");
+    }
+}
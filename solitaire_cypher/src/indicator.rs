@@ -0,0 +1,296 @@
+//! # Indicator
+//!
+//! Per-message keys.  Solitaire is a stream cypher so two messages encrypted with the same key deck
+//! share the same KeyStream, and subtracting one CypherText from the other cancels it out leaving
+//! the difference of the two PlainTexts (the messages are "in depth").  A message indicator is a
+//! short group of letters, random or a message counter, which is never reused with the same key.
+//! The key deck is further keyed with the indicator letters (as it is with passphrase letters)
+//! before the KeyStream is generated, so every message gets its own KeyStream while the
+//! passphrase or key deck stays the same.
+//!
+//! The indicator is sent as the first group(s) of the CypherText, either in the clear or encrypted
+//! with the start of the KeyStream of the unmodified key deck.  Encryption only hides indicators
+//! which can't be guessed: every indicator sent with the same key deck is encrypted with the same
+//! KeyStream letters, so the sent indicators are in depth with each other.  A single known or
+//! guessed indicator (e.g. a message counter) gives those KeyStream letters and so decrypts every
+//! other indicator.  The messages themselves stay out of depth either way.
+//!
+//! # Examples
+//! ```
+//! use std::str::FromStr;
+//! use solitaire_cypher::{key_deck_from_passphrase, Passphrase, PlainText};
+//! use solitaire_cypher::indicator::{
+//!     decrypt_with_indicator, encrypt_with_indicator, IndicatorMode, MessageIndicator,
+//! };
+//! let key_deck = key_deck_from_passphrase(&Passphrase::from_str("cryptonomicon").unwrap());
+//! let pt = PlainText::from_str("SOLITAIRE").unwrap();
+//! let first = MessageIndicator::from_counter(1, 5);
+//! let second = MessageIndicator::from_counter(2, 5);
+//! let ct_first = encrypt_with_indicator(key_deck.clone(), &first, IndicatorMode::Clear, &pt);
+//! let ct_second = encrypt_with_indicator(key_deck.clone(), &second, IndicatorMode::Clear, &pt);
+//! assert_eq!(ct_first.to_string(), "AAAAB FTERY AKBIZ");
+//! assert_eq!(ct_second.to_string(), "AAAAC GBCAN ISHSV");
+//! let decrypted = decrypt_with_indicator(key_deck, 5, IndicatorMode::Clear, &ct_second).unwrap();
+//! assert_eq!(decrypted.to_string(), "SOLITAIREX");
+//! ```
+
+use crate::{
+    decrypt, encrypt, get_key_stream, key_deck_with_letters, strict_letters, try_decrypt,
+    try_encrypt, CypherError, CypherText, PlainText, UpperLetter,
+};
+use card_play::Cards;
+use rand::Rng;
+use std::fmt;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Length of indicator that gives over 11 million different message keys
+pub const DEFAULT_INDICATOR_LENGTH: usize = 5;
+
+/// How the MessageIndicator is sent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IndicatorMode {
+    /// As is
+    #[default]
+    Clear,
+    /// Encrypted with the start of the KeyStream of the unmodified key deck, the same letters for
+    /// every message so one known indicator reveals the rest (see module documentation)
+    Encrypted,
+}
+
+/// Letters further keying the key deck of a single message
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessageIndicator(pub Vec<UpperLetter>);
+
+impl MessageIndicator {
+    /// A random indicator of len letters
+    pub fn random(len: usize) -> MessageIndicator {
+        MessageIndicator::random_with(len, &mut rand::thread_rng())
+    }
+
+    /// A random indicator of len letters drawn from the given random number generator, e.g. a
+    /// seeded one for reproducible tests
+    pub fn random_with(len: usize, rng: &mut impl Rng) -> MessageIndicator {
+        MessageIndicator(
+            (0..len)
+                // can panic if code broken - always A-Z
+                .map(|_| UpperLetter::new(rng.gen_range(b'A'..=b'Z')).unwrap())
+                .collect(),
+        )
+    }
+
+    /// The indicator of len letters numbering a message, counting in base 26 with A as 0 (wrapping
+    /// around after 26^len messages)
+    ///
+    /// # Examples
+    /// ```
+    /// use solitaire_cypher::indicator::MessageIndicator;
+    /// assert_eq!(MessageIndicator::from_counter(0, 5).to_string(), "AAAAA");
+    /// assert_eq!(MessageIndicator::from_counter(27, 5).to_string(), "AAABB");
+    /// assert_eq!(MessageIndicator::from_counter(26, 1).to_string(), "A");
+    /// ```
+    pub fn from_counter(counter: u64, len: usize) -> MessageIndicator {
+        let mut remaining = counter;
+        let mut letters = vec![UpperLetter::MIN; len];
+        for letter in letters.iter_mut().rev() {
+            // can panic if code broken - remainder is below 26
+            *letter = UpperLetter::new(b'A' + (remaining % 26) as u8).unwrap();
+            remaining /= 26;
+        }
+        MessageIndicator(letters)
+    }
+
+    #[allow(missing_docs)]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    #[allow(missing_docs)]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The key deck of the message: the key deck further keyed with each indicator letter in
+    /// turn, a Solitaire step followed by a count cut at the letter value, exactly as passphrase
+    /// letters key a deck
+    ///
+    /// # Examples
+    /// ```
+    /// use std::str::FromStr;
+    /// use solitaire_cypher::{key_deck_from_passphrase, Passphrase};
+    /// use solitaire_cypher::indicator::MessageIndicator;
+    /// let key_deck = key_deck_from_passphrase(&Passphrase::from_str("CRYPTO").unwrap());
+    /// let indicator = MessageIndicator::from_str("NOMICON").unwrap();
+    /// assert_eq!(
+    ///     indicator.key_deck(key_deck),
+    ///     key_deck_from_passphrase(&Passphrase::from_str("CRYPTONOMICON").unwrap())
+    /// );
+    /// ```
+    pub fn key_deck(&self, key_deck: Cards) -> Cards {
+        key_deck_with_letters(key_deck, &self.0, &mut |_, _, _| {})
+    }
+
+    /// The letters to send at the start of the CypherText
+    pub fn to_group(&self, key_deck: &Cards, mode: IndicatorMode) -> Vec<UpperLetter> {
+        match mode {
+            IndicatorMode::Clear => self.0.clone(),
+            IndicatorMode::Encrypted => {
+                let ks = get_key_stream(key_deck.clone(), self.len());
+                encrypt(&PlainText(self.0.clone()), &ks).0
+            }
+        }
+    }
+
+    /// The indicator sent as the given letters (see to_group())
+    ///
+    /// # Examples
+    /// ```
+    /// use std::str::FromStr;
+    /// use solitaire_cypher::{key_deck_from_passphrase, Passphrase};
+    /// use solitaire_cypher::indicator::{IndicatorMode, MessageIndicator};
+    /// let key_deck = key_deck_from_passphrase(&Passphrase::from_str("foo").unwrap());
+    /// let indicator = MessageIndicator::from_str("QWERT").unwrap();
+    /// let group = indicator.to_group(&key_deck, IndicatorMode::Encrypted);
+    /// assert_ne!(group, indicator.0);
+    /// assert_eq!(
+    ///     MessageIndicator::from_group(&group, &key_deck, IndicatorMode::Encrypted),
+    ///     indicator
+    /// );
+    /// ```
+    pub fn from_group(
+        group: &[UpperLetter],
+        key_deck: &Cards,
+        mode: IndicatorMode,
+    ) -> MessageIndicator {
+        match mode {
+            IndicatorMode::Clear => MessageIndicator(group.to_vec()),
+            IndicatorMode::Encrypted => {
+                let ks = get_key_stream(key_deck.clone(), group.len());
+                MessageIndicator(decrypt(&CypherText(group.to_vec()), &ks).0)
+            }
+        }
+    }
+}
+
+impl FromStr for MessageIndicator {
+    type Err = CypherError;
+
+    /// Creates a MessageIndicator from letters, ignoring whitespace
+    ///
+    /// returns Err CypherError::InvalidSymbols listing every other character
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(MessageIndicator(strict_letters(s)?))
+    }
+}
+
+impl Display for MessageIndicator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s: String = self.0.iter().map(|l| u8::from(*l) as char).collect();
+        write!(f, "{}", s)
+    }
+}
+
+/// Encrypt with the message key deck of the indicator, sending the indicator first (see module
+/// documentation)
+pub fn encrypt_with_indicator(
+    key_deck: Cards,
+    indicator: &MessageIndicator,
+    mode: IndicatorMode,
+    pt: &PlainText,
+) -> CypherText {
+    let mut ct = CypherText(indicator.to_group(&key_deck, mode));
+    let ks = get_key_stream(indicator.key_deck(key_deck), pt.len());
+    // can panic if code broken - the KeyStream is as long as the PlainText
+    ct.0.extend(try_encrypt(pt, &ks).unwrap().0);
+    ct
+}
+
+/// Decrypt a CypherText starting with an indicator of indicator_len letters (see
+/// encrypt_with_indicator())
+///
+/// returns Err CypherError::MissingIndicator if the CypherText is shorter than the indicator
+pub fn decrypt_with_indicator(
+    key_deck: Cards,
+    indicator_len: usize,
+    mode: IndicatorMode,
+    ct: &CypherText,
+) -> Result<PlainText, CypherError> {
+    if ct.len() < indicator_len {
+        return Err(CypherError::MissingIndicator {
+            needed: indicator_len,
+            available: ct.len(),
+        });
+    }
+    let (group, message) = ct.0.split_at(indicator_len);
+    let indicator = MessageIndicator::from_group(group, &key_deck, mode);
+    let message = CypherText(message.to_vec());
+    let ks = get_key_stream(indicator.key_deck(key_deck), message.len());
+    try_decrypt(&message, &ks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{key_deck_from_passphrase, KeyStream, Passphrase};
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_indicator_round_trip() {
+        let key_deck = key_deck_from_passphrase(&Passphrase::from_str("foo").unwrap());
+        let pt = PlainText::from_str("ATTACK AT DAWN").unwrap();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(21);
+        let mut seen = Vec::new();
+        for len in [0, 1, 5, 10] {
+            for mode in [IndicatorMode::Clear, IndicatorMode::Encrypted] {
+                let indicator = MessageIndicator::random_with(len, &mut rng);
+                let ct = encrypt_with_indicator(key_deck.clone(), &indicator, mode, &pt);
+                assert_eq!(ct.len(), len + pt.len());
+                let decrypted = decrypt_with_indicator(key_deck.clone(), len, mode, &ct).unwrap();
+                assert_eq!(decrypted.0, pt.0);
+                seen.push(ct.0[len..].to_vec());
+            }
+        }
+        // no indicator is plain Solitaire, different indicators give different KeyStreams
+        let plain = try_encrypt(&pt, &get_key_stream(key_deck.clone(), pt.len())).unwrap();
+        assert_eq!(seen[0], plain.0);
+        for (i, a) in seen.iter().enumerate().skip(2) {
+            assert!(seen[i + 1..].iter().all(|b| a != b));
+        }
+        assert_eq!(
+            decrypt_with_indicator(key_deck, 5, IndicatorMode::Clear, &CypherText(vec![]))
+                .unwrap_err(),
+            CypherError::MissingIndicator {
+                needed: 5,
+                available: 0
+            }
+        );
+    }
+    #[test]
+    fn test_encrypted_indicators_in_depth() {
+        let key_deck = key_deck_from_passphrase(&Passphrase::from_str("foo").unwrap());
+        let first = MessageIndicator::from_counter(41, 5);
+        let second = MessageIndicator::from_counter(42, 5);
+        let sent_first = first.to_group(&key_deck, IndicatorMode::Encrypted);
+        let sent_second = second.to_group(&key_deck, IndicatorMode::Encrypted);
+        assert_ne!(sent_first, first.0);
+        // the KeyStream cancels out of the difference of the sent indicators
+        let difference = |a: &[UpperLetter], b: &[UpperLetter]| -> Vec<u8> {
+            a.iter()
+                .zip(b)
+                .map(|(x, y)| (u8::from(*x) + 26 - u8::from(*y)) % 26)
+                .collect()
+        };
+        assert_eq!(
+            difference(&sent_first, &sent_second),
+            difference(&first.0, &second.0)
+        );
+        // guessing the first counter gives the KeyStream letters which decrypt the second
+        let ks = KeyStream(
+            difference(&sent_first, &first.0)
+                .iter()
+                .map(|d| UpperLetter::new(b'A' + (d + 25) % 26).unwrap())
+                .collect(),
+        );
+        assert_eq!(ks.0, get_key_stream(key_deck, 5).0);
+        assert_eq!(decrypt(&CypherText(sent_second), &ks).0, second.0);
+    }
+}
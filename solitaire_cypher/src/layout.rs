@@ -0,0 +1,267 @@
+//! # Layout
+//!
+//! Laying out CypherText and KeyStream letters for transmission or transcription.  Letters are
+//! traditionally sent in groups of five all on one line (as Display does) but long messages are
+//! easier to read, and check, when broken into lines of a fixed number of groups, optionally
+//! numbered.  The radiogram layout adds a header line giving the message number and group count
+//! (e.g. "NR 7 GR 12") so that lost or extra groups can be noticed (see
+//! GroupFormat::header_groups()), and ends with "AR" (end of message).
+//!
+//! ```text
+//! NR 7 GR 12
+//!  1  KIRAK SFJAN HXMTQ IWYVR
+//!  5  PQTBL ORFUY ZKFMW AZXIP
+//!  9  SDQKL FGHKO KHRTP TLXSU
+//! AR
+//! ```
+
+use crate::UpperLetter;
+
+/// How lines are numbered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Numbering {
+    /// No numbers
+    #[default]
+    None,
+    /// Each line starts with its line number
+    Lines,
+    /// Each line starts with the number of its first group
+    Groups,
+}
+
+/// Layout of letters in groups and lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupFormat {
+    /// Number of letters in each group, 0 for no groups (the letters run together)
+    pub group_size: usize,
+    /// Number of groups on each line, 0 for all on one line
+    pub groups_per_line: usize,
+    /// How lines are numbered
+    pub numbering: Numbering,
+    /// Radiogram layout with this message number, see module documentation
+    pub radiogram: Option<u32>,
+}
+
+impl Default for GroupFormat {
+    /// Groups of five all on one line as Display does
+    fn default() -> Self {
+        GroupFormat {
+            group_size: 5,
+            groups_per_line: 0,
+            numbering: Numbering::None,
+            radiogram: None,
+        }
+    }
+}
+
+impl GroupFormat {
+    /// Lay out the letters, e.g. of a CypherText or KeyStream, without a final line ending
+    ///
+    /// # Examples
+    /// ```
+    /// use std::str::FromStr;
+    /// use solitaire_cypher::CypherText;
+    /// use solitaire_cypher::layout::{GroupFormat, Numbering};
+    /// let ct = CypherText::from_str("KIRAKSFJANHXMTQIWYVRPQ").unwrap();
+    /// assert_eq!(GroupFormat::default().format(&ct.0), ct.to_string());
+    /// let format = GroupFormat {
+    ///     group_size: 4,
+    ///     groups_per_line: 2,
+    ///     numbering: Numbering::Lines,
+    ///     radiogram: Some(3),
+    /// };
+    /// assert_eq!(
+    ///     format.format(&ct.0),
+    ///     "NR 3 GR 6\n1  KIRA KSFJ\n2  ANHX MTQI\n3  WYVR PQ\nAR"
+    /// );
+    /// ```
+    pub fn format(&self, letters: &[UpperLetter]) -> String {
+        let group_size = if self.group_size == 0 {
+            letters.len().max(1)
+        } else {
+            self.group_size
+        };
+        let groups: Vec<String> = letters
+            .chunks(group_size)
+            .map(|group| group.iter().map(|l| u8::from(*l) as char).collect())
+            .collect();
+        let groups_per_line = if self.groups_per_line == 0 {
+            groups.len().max(1)
+        } else {
+            self.groups_per_line
+        };
+        let line_count = groups.len().div_ceil(groups_per_line);
+        let width = match self.numbering {
+            Numbering::None => 0,
+            Numbering::Lines => line_count.to_string().len(),
+            Numbering::Groups => ((line_count.max(1) - 1) * groups_per_line + 1)
+                .to_string()
+                .len(),
+        };
+
+        let mut lines = Vec::with_capacity(line_count + 2);
+        if let Some(number) = self.radiogram {
+            lines.push(format!("NR {} GR {}", number, groups.len()));
+        }
+        for (i, line) in groups.chunks(groups_per_line).enumerate() {
+            let number = match self.numbering {
+                Numbering::None => None,
+                Numbering::Lines => Some(i + 1),
+                Numbering::Groups => Some(i * groups_per_line + 1),
+            };
+            lines.push(match number {
+                Some(number) => format!("{:>width$}  {}", number, line.join(" ")),
+                None => line.join(" "),
+            });
+        }
+        if self.radiogram.is_some() {
+            lines.push("AR".to_string());
+        }
+        lines.join("\n")
+    }
+
+    /// Remove the line numbers and radiogram header and ending added by format() leaving just the
+    /// letters and whitespace, e.g. for CypherText::from_str_strict()
+    ///
+    /// # Examples
+    /// ```
+    /// use solitaire_cypher::CypherText;
+    /// use solitaire_cypher::layout::{GroupFormat, Numbering};
+    /// let format = GroupFormat {
+    ///     numbering: Numbering::Groups,
+    ///     radiogram: Some(1),
+    ///     ..Default::default()
+    /// };
+    /// let text = "NR 1 GR 2\n1  KIRAK\n2  SFJAN\nAR\n";
+    /// let ct = CypherText::from_str_strict(&format.unformat(text)).unwrap();
+    /// assert_eq!(ct.to_string(), "KIRAK SFJAN");
+    /// ```
+    pub fn unformat(&self, text: &str) -> String {
+        let mut lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+        if self.radiogram.is_some() {
+            if lines
+                .first()
+                .is_some_and(|l| l.trim_start().starts_with("NR "))
+            {
+                lines.remove(0);
+            }
+            if lines.last().is_some_and(|l| l.trim() == "AR") {
+                lines.pop();
+            }
+        }
+        if self.numbering != Numbering::None {
+            for line in lines.iter_mut() {
+                let trimmed = line.trim_start();
+                *line = trimmed.trim_start_matches(|c: char| c.is_ascii_digit());
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// The group count given by the radiogram header ("NR n GR g") at the start of the text, None
+    /// if not the radiogram layout or the header is missing or malformed.  Compare with the number
+    /// of groups received, e.g. unformat(text).split_whitespace().count(), to notice lost or extra
+    /// groups.
+    ///
+    /// # Examples
+    /// ```
+    /// use solitaire_cypher::layout::GroupFormat;
+    /// let format = GroupFormat {
+    ///     radiogram: Some(1),
+    ///     ..Default::default()
+    /// };
+    /// let text = "NR 1 GR 3\nKIRAK SFJAN\nAR\n";
+    /// assert_eq!(format.header_groups(text), Some(3));
+    /// assert_eq!(format.unformat(text).split_whitespace().count(), 2);
+    /// assert_eq!(GroupFormat::default().header_groups(text), None);
+    /// ```
+    pub fn header_groups(&self, text: &str) -> Option<usize> {
+        self.radiogram?;
+        let header = text.lines().find(|l| !l.trim().is_empty())?;
+        match header.split_whitespace().collect::<Vec<&str>>()[..] {
+            ["NR", _, "GR", groups] => groups.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyStream;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_format_round_trip() {
+        let ks = KeyStream::from_str(&"ABCDEFGHIJKLMNOPQRSTUVWXYZ".repeat(5)).unwrap();
+        for group_size in [0, 1, 5, 7] {
+            for groups_per_line in [0, 1, 10] {
+                for numbering in [Numbering::None, Numbering::Lines, Numbering::Groups] {
+                    for radiogram in [None, Some(42)] {
+                        let format = GroupFormat {
+                            group_size,
+                            groups_per_line,
+                            numbering,
+                            radiogram,
+                        };
+                        let text = format.format(&ks.0);
+                        let parsed = KeyStream::from_str_strict(&format.unformat(&text));
+                        assert_eq!(parsed.unwrap().0, ks.0, "{:?}\n{}", format, text);
+                        if radiogram.is_some() {
+                            assert_eq!(
+                                format.header_groups(&text),
+                                Some(format.unformat(&text).split_whitespace().count()),
+                                "{:?}\n{}",
+                                format,
+                                text
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        let ks = KeyStream::from_str(&"ABCDE".repeat(21)).unwrap();
+        let format = GroupFormat {
+            groups_per_line: 10,
+            numbering: Numbering::Groups,
+            ..Default::default()
+        };
+        let text = format.format(&ks.0);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with(" 1  ABCDE ABCDE"));
+        assert!(lines[1].starts_with("11  ABCDE"));
+        assert_eq!(lines[2], "21  ABCDE");
+        let format = GroupFormat {
+            groups_per_line: 1,
+            numbering: Numbering::Groups,
+            ..Default::default()
+        };
+        let text = format.format(&ks.0[..50]);
+        assert!(text.starts_with(" 1  ABCDE\n 2  ABCDE"));
+        assert!(text.ends_with("\n10  ABCDE"));
+        // empty still gives the radiogram header
+        let format = GroupFormat {
+            radiogram: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(format.format(&[]), "NR 1 GR 0\nAR");
+    }
+
+    #[test]
+    fn test_header_groups() {
+        let format = GroupFormat {
+            radiogram: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(format.header_groups("\n  NR 12 GR 34\nABCDE\nAR"), Some(34));
+        assert_eq!(format.header_groups("NR 12 GR\nABCDE\nAR"), None);
+        assert_eq!(format.header_groups("NR 12 GR X\nABCDE\nAR"), None);
+        assert_eq!(format.header_groups("ABCDE\nAR"), None);
+        assert_eq!(format.header_groups(""), None);
+    }
+}
@@ -14,10 +14,28 @@ use std::convert::Infallible;
 use std::fmt;
 use std::fmt::Display;
 use std::str::FromStr;
+use trace::SubStep;
+
+pub mod analysis;
+pub mod deck_state;
+pub mod depth;
+pub mod diagnose;
+pub mod encoding;
+pub mod indicator;
+pub mod layout;
+pub mod recovery;
+pub mod stats;
+pub mod trace;
+pub mod transliterate;
+pub mod vectors;
 
 /// Bounded type for ascii uppercase values A-Z
 pub type UpperLetter = BoundedU8<65, 90>;
 
+/// The published Solitaire test vectors (the sol-test.txt file from Schneier's site), each a
+/// Plaintext, Key, Output and Ciphertext line followed by a blank line (see vectors module)
+pub const TEST_VECTORS: &str = include_str!("../sol-test.txt");
+
 fn letter_into_value(ul: &UpperLetter) -> LetterValue {
     // can panic if UpperLetter bounds code or next line broken
     LetterValue::new(u8::from(*ul) - 64).unwrap()
@@ -30,7 +48,9 @@ fn value_into_letter(lv: &LetterValue) -> UpperLetter {
 
 type LetterValue = BoundedU8<1, 26>;
 
-type CardValue = BoundedU8<1, 53>;
+/// Bounded type for the Solitaire value of a card 1-53.  Cards are valued in new deck order
+/// (see card_play::Cards::new()) with both jokers valued 53
+pub type CardValue = BoundedU8<1, 53>;
 
 fn card_val_into_position(cv: &CardValue) -> CardPosition {
     // can panic if value vs position bounds broken (value bounds must be >= position)
@@ -45,6 +65,152 @@ fn card_val_into_let_val(cv: CardValue) -> LetterValue {
 
 type CardPosition = BoundedU8<1, 54>;
 
+/// Errors returned by the solitaire_cypher functions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CypherError {
+    /// The KeyStream has fewer letters than needed, e.g. than the text being encrypted or decrypted
+    KeyStreamTooShort {
+        /// Number of KeyStream letters required (e.g. the text length)
+        needed: usize,
+        /// Number of KeyStream letters supplied
+        available: usize,
+    },
+    /// A character other than a letter was found where only letters are allowed
+    NonLetter {
+        /// Zero based character (not byte) position within the string
+        position: usize,
+        /// The offending character
+        character: char,
+    },
+    /// A card name which isn't a card was found in a key deck
+    UnknownCard {
+        /// Zero based card position within the deck
+        position: usize,
+        /// The offending card name
+        name: String,
+    },
+    /// A key deck is not exactly one of each of the 52 cards and the two jokers
+    IncompleteDeck {
+        /// Cards not found in the deck, in new deck order
+        missing: Vec<Card>,
+        /// Cards found more than once in the deck, in new deck order
+        duplicate: Vec<Card>,
+    },
+    /// The end of a PlainText is not padding of the expected Padding scheme
+    BadPadding,
+    /// Escape encoded text ends with an escape letter missing its code letter
+    UnfinishedEscape,
+    /// Characters other than letters and whitespace were found by a strict constructor
+    InvalidSymbols(Vec<InvalidSymbol>),
+    /// A CypherText is too short to hold its message indicator
+    MissingIndicator {
+        /// Number of indicator letters expected
+        needed: usize,
+        /// Number of letters in the CypherText
+        available: usize,
+    },
+}
+
+/// A character other than a letter or whitespace found by a strict constructor (e.g.
+/// CypherText::from_str_strict())
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidSymbol {
+    /// Zero based character (not byte) position within the string
+    pub position: usize,
+    /// The offending character
+    pub character: char,
+}
+
+impl Display for CypherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CypherError::KeyStreamTooShort { needed, available } => write!(
+                f,
+                "KeyStream not long enough: {} letters needed, {} available",
+                needed, available
+            ),
+            CypherError::NonLetter {
+                position,
+                character,
+            } => write!(
+                f,
+                "string contains non-letter {:?} at position {}",
+                character, position
+            ),
+            CypherError::UnknownCard { position, name } => {
+                write!(f, "unknown card {:?} at position {}", name, position)
+            }
+            CypherError::IncompleteDeck { missing, duplicate } => {
+                let names = |cards: &[Card]| -> String {
+                    cards
+                        .iter()
+                        .map(|c| c.to_string())
+                        .collect::<Vec<String>>()
+                        .join(" ")
+                };
+                write!(f, "key deck is not a complete 54 card deck:")?;
+                if !missing.is_empty() {
+                    write!(f, " missing {}", names(missing))?;
+                }
+                if !missing.is_empty() && !duplicate.is_empty() {
+                    write!(f, ";")?;
+                }
+                if !duplicate.is_empty() {
+                    write!(f, " duplicate {}", names(duplicate))?;
+                }
+                Ok(())
+            }
+            CypherError::BadPadding => write!(f, "text does not end with the expected padding"),
+            CypherError::UnfinishedEscape => write!(f, "text ends with an unfinished escape"),
+            CypherError::MissingIndicator { needed, available } => write!(
+                f,
+                "CypherText too short for its message indicator: {} letters needed, {} available",
+                needed, available
+            ),
+            CypherError::InvalidSymbols(invalid) => {
+                write!(f, "string contains invalid symbols:")?;
+                for (i, symbol) in invalid.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { "," };
+                    write!(
+                        f,
+                        "{} {:?} at position {}",
+                        separator, symbol.character, symbol.position
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for CypherError {}
+
+// The letters of s, mapped to upper case, skipping whitespace.  Err listing every other character.
+fn strict_letters(s: &str) -> Result<Vec<UpperLetter>, CypherError> {
+    let mut letters = Vec::with_capacity(s.len());
+    let mut invalid = Vec::new();
+    for (position, character) in s.chars().enumerate() {
+        if character.is_whitespace() {
+            continue;
+        }
+        match u8::try_from(character.to_ascii_uppercase())
+            .ok()
+            .and_then(UpperLetter::new)
+        {
+            Some(l) => letters.push(l),
+            None => invalid.push(InvalidSymbol {
+                position,
+                character,
+            }),
+        }
+    }
+    if invalid.is_empty() {
+        Ok(letters)
+    } else {
+        Err(CypherError::InvalidSymbols(invalid))
+    }
+}
+
 /// Container for an ordered collection of UpperLetters intended as plaintext
 #[derive(Debug, Clone, Default)]
 pub struct PlainText(pub Vec<UpperLetter>);
@@ -62,6 +228,147 @@ impl PlainText {
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
+
+    /// Creates a PlainText from a slice of letters as PlainText::from_str() does, but without
+    /// padding (see pad()).  Accented and other non-ASCII letters are transliterated (see
+    /// transliterate::transliterate_char()) and anything else is ignored.
+    ///
+    /// # Examples
+    /// ```
+    /// use solitaire_cypher::PlainText;
+    /// assert_eq!(PlainText::from_letters("Don't use PC").to_string(), "DONTUSEPC");
+    /// assert_eq!(PlainText::from_letters("Café Müller").to_string(), "CAFEMULLER");
+    /// ```
+    pub fn from_letters(s: &str) -> PlainText {
+        let mut pt = PlainText(Vec::new());
+        for character in s.chars() {
+            transliterate::transliterate_char(character, |c| {
+                if let Some(l) = UpperLetter::new(c.to_ascii_uppercase() as u8) {
+                    pt.0.push(l);
+                }
+            });
+        }
+        pt
+    }
+
+    /// Creates a PlainText as PlainText::from_str() does but only allowing ASCII letters and
+    /// whitespace
+    ///
+    /// returns Err CypherError::InvalidSymbols listing every other character
+    ///
+    /// # Examples
+    /// ```
+    /// use solitaire_cypher::{CypherError, InvalidSymbol, PlainText};
+    /// assert_eq!(PlainText::from_str_strict("Meet at\nnoon").unwrap().to_string(), "MEETATNOON");
+    /// assert_eq!(
+    ///     PlainText::from_str_strict("at 10").unwrap_err(),
+    ///     CypherError::InvalidSymbols(vec![
+    ///         InvalidSymbol { position: 3, character: '1' },
+    ///         InvalidSymbol { position: 4, character: '0' },
+    ///     ])
+    /// );
+    /// ```
+    pub fn from_str_strict(s: &str) -> Result<PlainText, CypherError> {
+        let mut pt = PlainText(strict_letters(s)?);
+        pt.pad(Padding::default());
+        Ok(pt)
+    }
+
+    /// Pad the end so the length is a multiple of five (as is the crypto tradition) using the
+    /// given Padding scheme
+    ///
+    /// # Examples
+    /// ```
+    /// use solitaire_cypher::{Padding, PlainText, UpperLetter};
+    /// let mut pt = PlainText::from_letters("CIPHER");
+    /// pt.pad(Padding::Marked(UpperLetter::new(b'X').unwrap()));
+    /// assert_eq!(pt.to_string(), "CIPHERXXXD");
+    /// ```
+    pub fn pad(&mut self, padding: Padding) {
+        match padding {
+            Padding::Letter(pad) => {
+                while !self.0.len().is_multiple_of(5) {
+                    self.0.push(pad);
+                }
+            }
+            Padding::Marked(pad) => {
+                let count = 5 - self.0.len() % 5;
+                self.0.extend(std::iter::repeat_n(pad, count - 1));
+                // can panic if code broken - count is 1-5 so the marker is A-E
+                self.0
+                    .push(UpperLetter::new(b'A' + count as u8 - 1).unwrap());
+            }
+            Padding::None => {}
+        }
+    }
+
+    /// Remove the padding added by pad() with the given Padding scheme.  Padding::Letter can't be
+    /// told apart from the message so up to four trailing pad letters are removed, whereas
+    /// Padding::Marked is removed exactly.
+    ///
+    /// returns Err CypherError::BadPadding if the text doesn't end with Padding::Marked padding
+    ///
+    /// # Examples
+    /// ```
+    /// use std::str::FromStr;
+    /// use solitaire_cypher::{CypherError, Padding, PlainText, UpperLetter};
+    /// let x = UpperLetter::new(b'X').unwrap();
+    /// let mut pt = PlainText::from_letters("CIPHERXXXD");
+    /// pt.strip_padding(Padding::Marked(x)).unwrap();
+    /// assert_eq!(pt.to_string(), "CIPHER");
+    /// let mut pt = PlainText::from_str("SOLITAIRE").unwrap();
+    /// assert_eq!(pt.strip_padding(Padding::Marked(x)), Err(CypherError::BadPadding));
+    /// pt.strip_padding(Padding::Letter(x)).unwrap();
+    /// assert_eq!(pt.to_string(), "SOLITAIRE");
+    /// ```
+    pub fn strip_padding(&mut self, padding: Padding) -> Result<(), CypherError> {
+        match padding {
+            Padding::Letter(pad) => {
+                for _ in 0..4 {
+                    if self.0.last() != Some(&pad) {
+                        break;
+                    }
+                    self.0.pop();
+                }
+            }
+            Padding::Marked(pad) => {
+                let count = match self.0.last().map(|l| u8::from(*l)) {
+                    Some(marker @ b'A'..=b'E') => usize::from(marker - b'A') + 1,
+                    _ => return Err(CypherError::BadPadding),
+                };
+                if count > self.0.len() {
+                    return Err(CypherError::BadPadding);
+                }
+                let start = self.0.len() - count;
+                if self.0[start..self.0.len() - 1].iter().any(|l| *l != pad) {
+                    return Err(CypherError::BadPadding);
+                }
+                self.0.truncate(start);
+            }
+            Padding::None => {}
+        }
+        Ok(())
+    }
+}
+
+/// Schemes for padding PlainText out to a multiple of five letters (see PlainText::pad())
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Padding {
+    /// Add up to four of the letter (traditionally X).  The padding can't be told apart from a
+    /// message ending in the same letter.
+    Letter(UpperLetter),
+    /// Always add one to five letters, the last being a marker A-E giving how many letters were
+    /// added and the rest the letter, so that the padding can be removed exactly
+    Marked(UpperLetter),
+    /// Add nothing, the last group of five may be short
+    None,
+}
+
+impl Default for Padding {
+    fn default() -> Self {
+        // can panic if code broken - X is a letter
+        Padding::Letter(UpperLetter::new(b'X').unwrap())
+    }
 }
 
 impl Display for PlainText {
@@ -91,16 +398,8 @@ impl FromStr for PlainText {
     /// println!("CypherText: {}", ct_result.unwrap().to_string());
     /// ```
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut pt = PlainText(Vec::new());
-        for letter in s.to_uppercase().bytes() {
-            match UpperLetter::new(letter) {
-                Some(l) => pt.0.push(l),
-                None => continue,
-            }
-        }
-        while pt.0.len() % 5 != 0 {
-            pt.0.push(UpperLetter::new(b'X').unwrap());
-        }
+        let mut pt = PlainText::from_letters(s);
+        pt.pad(Padding::default());
         Ok(pt)
     }
 }
@@ -121,6 +420,39 @@ impl CypherText {
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
+
+    /// Creates a CypherText from letters and whitespace, e.g. groups of five, failing on anything
+    /// else (unlike CypherText::from_str() which skips it)
+    ///
+    /// returns Err CypherError::InvalidSymbols listing every character other than a letter or
+    /// whitespace
+    ///
+    /// # Examples
+    /// ```
+    /// use solitaire_cypher::{CypherError, CypherText, InvalidSymbol};
+    /// let ct = CypherText::from_str_strict("kirak sfjan").unwrap();
+    /// assert_eq!(ct.to_string(), "KIRAK SFJAN");
+    /// assert_eq!(
+    ///     CypherText::from_str_strict("KIR4K SFJAN").unwrap_err(),
+    ///     CypherError::InvalidSymbols(vec![InvalidSymbol { position: 3, character: '4' }])
+    /// );
+    /// ```
+    pub fn from_str_strict(s: &str) -> Result<CypherText, CypherError> {
+        Ok(CypherText(strict_letters(s)?))
+    }
+
+    /// Whether the length is a multiple of five as a padded CypherText's is.  If not, letters may
+    /// have been lost or added in transmission and a warning is appropriate.
+    ///
+    /// # Examples
+    /// ```
+    /// use solitaire_cypher::CypherText;
+    /// assert!(CypherText::from_str_strict("KIRAK SFJAN").unwrap().is_whole_groups());
+    /// assert!(!CypherText::from_str_strict("KIRAK SFJA").unwrap().is_whole_groups());
+    /// ```
+    pub fn is_whole_groups(&self) -> bool {
+        self.0.len().is_multiple_of(5)
+    }
 }
 
 impl FromStr for CypherText {
@@ -151,30 +483,36 @@ impl FromStr for CypherText {
 }
 
 impl Display for CypherText {
+    /// Groups of five all on one line, see layout::GroupFormat for other layouts
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut s = String::new();
-        for (i, c) in self.0.iter().enumerate() {
-            if i != 0 && i % 5 == 0 {
-                s.push(' ');
-            }
-            s.push(u8::from(*c) as char);
-        }
-        write!(f, "{}", s)
+        write!(f, "{}", layout::GroupFormat::default().format(&self.0))
     }
 }
 
-/// Container for an ordered collection of UpperLetters intended for use as a passphrase
+/// Container for an ordered collection of UpperLetters intended for use as a passphrase.  The
+/// letters are overwritten when the Passphrase is dropped so the key doesn't linger in freed
+/// memory, which is why they can only be borrowed (see letters()) and not moved out.
 #[derive(Debug, Default)]
-pub struct Passphrase(pub Vec<UpperLetter>);
+pub struct Passphrase(Vec<UpperLetter>);
 
 impl Passphrase {
-    fn iter(&self) -> std::slice::Iter<'_, UpperLetter> {
-        self.0.iter()
-    }
     #[allow(missing_docs)]
     pub fn new() -> Passphrase {
         Passphrase(Vec::new())
     }
+    /// The letters of the passphrase
+    ///
+    /// # Examples
+    /// ```
+    /// use std::str::FromStr;
+    /// use solitaire_cypher::Passphrase;
+    /// let passphrase = Passphrase::from_str("foo").unwrap();
+    /// assert_eq!(passphrase.letters().len(), 3);
+    /// assert_eq!(u8::from(passphrase.letters()[0]), b'F');
+    /// ```
+    pub fn letters(&self) -> &[UpperLetter] {
+        &self.0
+    }
     #[allow(missing_docs)]
     pub fn len(&self) -> usize {
         self.0.len()
@@ -186,36 +524,74 @@ impl Passphrase {
 }
 
 impl FromStr for Passphrase {
-    type Err = &'static str;
+    type Err = CypherError;
 
     /// Creates a Passphrase from a slice of letters - all lower case letters mapped to upper during
-    /// creation.  Single quotes are removed to ease use of wikipedia's solitaire cypher test vectors
+    /// creation and accented and other non-ASCII letters transliterated (see
+    /// transliterate::transliterate_char()).  Single quotes are removed to ease use of wikipedia's
+    /// solitaire cypher test vectors
     ///
-    /// returns Err string if non-letters are encountered in the slice
+    /// returns Err CypherError::NonLetter if non-letters (or letters that can't be transliterated)
+    /// are encountered in the slice
     ///
     /// # Examples
     /// ```
     /// use std::str::FromStr;
-    /// use solitaire_cypher::Passphrase;
+    /// use solitaire_cypher::{CypherError, Passphrase};
     /// let ps_result = Passphrase::from_str("cryptoNOMicon");
     /// assert!(ps_result.is_ok());
     /// println!("Passphrase: {}",ps_result.unwrap().to_string());
-    /// assert!(Passphrase::from_str("crypto NOMicon").is_err());
+    /// assert_eq!(
+    ///     Passphrase::from_str("crypto NOMicon").unwrap_err(),
+    ///     CypherError::NonLetter { position: 6, character: ' ' }
+    /// );
+    /// assert_eq!(Passphrase::from_str("Straße").unwrap().to_string(), "STRASSE");
     /// ```
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut pp = Passphrase(Vec::new());
-        let s = &remove_ticks(s);
-        for letter in s.to_uppercase().bytes() {
-            if let Some(l) = UpperLetter::new(letter) {
-                pp.0.push(l);
-            } else {
-                return Err("string contains non-letter");
+        // Checked and counted first so the letters are pushed into a Vec already at its final size,
+        // which never reallocates leaving a copy of them behind (a transliterated spelling can be
+        // longer than the UTF-8 it replaces, e.g. "ⅷ" is "VIII")
+        let mut count = 0;
+        for (position, character) in s.chars().enumerate() {
+            let mut letters = true;
+            let mapped = transliterate::transliterate_char(character, |c| {
+                if c == '\'' {
+                    return;
+                }
+                match UpperLetter::new(c.to_ascii_uppercase() as u8) {
+                    Some(_) => count += 1,
+                    None => letters = false,
+                }
+            });
+            if !mapped || !letters {
+                return Err(CypherError::NonLetter {
+                    position,
+                    character,
+                });
             }
         }
+        let mut pp = Passphrase(Vec::with_capacity(count));
+        for character in s.chars() {
+            transliterate::transliterate_char(character, |c| {
+                if let Some(l) = UpperLetter::new(c.to_ascii_uppercase() as u8) {
+                    pp.0.push(l);
+                }
+            });
+        }
         Ok(pp)
     }
 }
 
+impl Drop for Passphrase {
+    fn drop(&mut self) {
+        for letter in self.0.iter_mut() {
+            *letter = UpperLetter::MIN;
+        }
+        // keep the overwrite from being optimized away as a dead store
+        std::hint::black_box(&mut self.0);
+    }
+}
+
 impl Display for Passphrase {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut s = String::new();
@@ -243,18 +619,31 @@ impl KeyStream {
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
+
+    /// Creates a KeyStream from letters and whitespace, failing on anything else (unlike
+    /// KeyStream::from_str() which skips it)
+    ///
+    /// returns Err CypherError::InvalidSymbols listing every character other than a letter or
+    /// whitespace
+    ///
+    /// # Examples
+    /// ```
+    /// use solitaire_cypher::{CypherError, InvalidSymbol, KeyStream};
+    /// assert_eq!(KeyStream::from_str_strict("dwjxh").unwrap().to_string(), "DWJXH");
+    /// assert_eq!(
+    ///     KeyStream::from_str_strict("DW-XH").unwrap_err(),
+    ///     CypherError::InvalidSymbols(vec![InvalidSymbol { position: 2, character: '-' }])
+    /// );
+    /// ```
+    pub fn from_str_strict(s: &str) -> Result<KeyStream, CypherError> {
+        Ok(KeyStream(strict_letters(s)?))
+    }
 }
 
 impl Display for KeyStream {
+    /// Groups of five all on one line, see layout::GroupFormat for other layouts
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut s = String::new();
-        for (i, c) in self.0.iter().enumerate() {
-            if i != 0 && i % 5 == 0 {
-                s.push(' ');
-            }
-            s.push(u8::from(*c) as char);
-        }
-        write!(f, "{}", s)
+        write!(f, "{}", layout::GroupFormat::default().format(&self.0))
     }
 }
 
@@ -264,7 +653,6 @@ impl FromStr for KeyStream {
     /// Creates a KeyStream from a slice of letters - lower case letters are mapped to upper during
     /// creation.
     ///
-    ///impl Display for PlainText {
     /// # Examples
     /// ```
     /// use std::str::FromStr;
@@ -298,6 +686,19 @@ impl Value for Card {
     }
 }
 
+/// Obtain the Solitaire value of a card - 1 through 52 in new deck order with both jokers 53
+///
+/// # Examples
+/// ```
+/// use card_play::{Card, JokerId, Suit};
+/// use solitaire_cypher::{card_value, CardValue};
+/// assert_eq!(card_value(&Card::Ace(Suit::Heart)), CardValue::new(1).unwrap());
+/// assert_eq!(card_value(&Card::Joker(JokerId::B)), CardValue::new(53).unwrap());
+/// ```
+pub fn card_value(card: &Card) -> CardValue {
+    card.value()
+}
+
 fn value_init() -> HashMap<Card, CardValue> {
     let mut values = HashMap::new();
     // can panic if next line broken - illegal value for JokersPerDeck
@@ -315,14 +716,117 @@ fn value_init() -> HashMap<Card, CardValue> {
     values
 }
 
-fn next_deck_state(mut key_deck: Cards) -> Cards {
-    // A Joker move
+/// Perform the deck changing steps of the Solitaire algorithm (Joker A move, Joker B move,
+/// triple cut and count cut) returning the resulting deck.  The output card is then found from
+/// this deck (see KeystreamGenerator).
+///
+/// # Examples
+/// ```
+/// use card_play::{Cards, JokersPerDeck};
+/// use solitaire_cypher::next_deck_state;
+/// let deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+/// let next = next_deck_state(deck);
+/// assert_eq!(next.to_string().split(' ').next(), Some("2H"));
+/// ```
+pub fn next_deck_state(key_deck: Cards) -> Cards {
+    next_deck_state_with(key_deck, &mut |_, _| {})
+}
+
+/// Undo the deck changing steps of the Solitaire algorithm (see next_deck_state()), returning
+/// every deck which next_deck_state() takes to the given deck.  Usually there is exactly one.
+/// There are two (or more) when a joker could have reached its position either directly or by
+/// wrapping around from the bottom of the deck, and none if the deck can't be reached (e.g.
+/// Joker A is directly below the top card as a consequence of wrapping).
+///
+/// # Examples
+/// ```
+/// use card_play::{Cards, JokersPerDeck};
+/// use solitaire_cypher::{next_deck_state, previous_deck_state};
+/// let deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+/// let next = next_deck_state(deck.clone());
+/// assert_eq!(previous_deck_state(&next), vec![deck]);
+/// ```
+pub fn previous_deck_state(key_deck: &Cards) -> Vec<Cards> {
+    let len = key_deck.len();
+    // Undo the count cut: the bottom card is unchanged so its value gives the cut made
+    // can panic if code broken - deck should always have a bottom card
+    let bottom_card_value = usize::from(key_deck.look_at(len - 1).unwrap().value());
+    if bottom_card_value >= len {
+        return vec![];
+    }
+    let deck = count_cut(key_deck.clone(), len - 1 - bottom_card_value);
+    // The triple cut is its own inverse
+    let deck = triple_cut(deck);
+
+    // Undo the joker moves, B then A, each of which may have more than one source position
+    let mut candidates = Vec::new();
+    for before_b in unmove_card_circular(&deck, Card::Joker(JokerId::B), 2) {
+        candidates.append(&mut unmove_card_circular(
+            &before_b,
+            Card::Joker(JokerId::A),
+            1,
+        ));
+    }
+    // Confirm each candidate as moving a joker can pass over the other one
+    let mut previous: Vec<Cards> = Vec::new();
+    for candidate in candidates {
+        if !previous.contains(&candidate) && next_deck_state(candidate.clone()) == *key_deck {
+            previous.push(candidate);
+        }
+    }
+    previous
+}
+
+// Every deck from which Cards::move_card_circular(card, 0, distance) gives the deck provided
+fn unmove_card_circular(key_deck: &Cards, card: Card, distance: isize) -> Vec<Cards> {
+    let mut sources = Vec::new();
+    let Some(position) = key_deck.find(card) else {
+        return sources;
+    };
+    for start in 0..key_deck.len() {
+        let mut deck = key_deck.clone();
+        let card = deck.0.remove(position);
+        deck.0.insert(start, card);
+        let mut moved = deck.clone();
+        if moved.move_card_circular(card, 0, distance) && moved == *key_deck {
+            sources.push(deck);
+        }
+    }
+    sources
+}
+
+// Perform the four deck changing steps of the Solitaire algorithm, calling observer with the deck
+// after each of them (see trace module)
+fn next_deck_state_with(mut key_deck: Cards, observer: &mut impl FnMut(SubStep, &Cards)) -> Cards {
+    move_joker_a(&mut key_deck);
+    observer(SubStep::JokerA, &key_deck);
+    move_joker_b(&mut key_deck);
+    observer(SubStep::JokerB, &key_deck);
+    key_deck = triple_cut(key_deck);
+    observer(SubStep::TripleCut, &key_deck);
+
+    // Count cut based on the value of the bottom card leaving the bottom card at the bottom
+    let bottom_card_value = key_deck
+        // can panic if code broken - following line doesn't point to location with a card in deck
+        .look_at(key_deck.0.len() - 1)
+        .unwrap()
+        .value();
+    key_deck = count_cut(key_deck, bottom_card_value.into());
+    observer(SubStep::CountCut, &key_deck);
+    key_deck
+}
+
+fn move_joker_a(key_deck: &mut Cards) {
     assert!(key_deck.move_card_circular(Card::Joker(JokerId::A), 0, 1));
-    // B Joker move
+}
+
+fn move_joker_b(key_deck: &mut Cards) {
     assert!(key_deck.move_card_circular(Card::Joker(JokerId::B), 0, 2));
+}
 
-    // Triple cut at Jokers (aka fools. fa, fb being fool A and fool B respectively)
-    // and swap top with bottom leaving Jokers in place
+// Triple cut at Jokers (aka fools. fa, fb being fool A and fool B respectively)
+// and swap top with bottom leaving Jokers in place
+fn triple_cut(mut key_deck: Cards) -> Cards {
     // can panic if code broken - required joker not present
     let mut above_fa = key_deck.draw_till(Card::Joker(JokerId::A)).unwrap();
     if let Some(above_both) = above_fa.draw_till(Card::Joker(JokerId::B)) {
@@ -346,14 +850,13 @@ fn next_deck_state(mut key_deck: Cards) -> Cards {
         key_deck.append(fb);
         key_deck.append(above_fa);
     }
+    key_deck
+}
 
-    // Count cut based on the value of the bottom card leaving the bottom card at the bottom
-    let bottom_card_value = &key_deck.
-        // can panic if code broken - following line doesn't point to location with a card in deck
-        look_at(key_deck.0.len() - 1)
-        .unwrap()
-        .value();
-    let TwoStacks(top, mut bottom) = key_deck.cut((*bottom_card_value).into());
+// Count cut moving the top count cards to just above the bottom card, leaving the bottom card
+// at the bottom
+fn count_cut(key_deck: Cards, count: usize) -> Cards {
+    let TwoStacks(top, mut bottom) = key_deck.cut(count);
     let bottom_card = bottom
         .0
         // can panic if code broken - bottom should have at least the bottom card
@@ -361,11 +864,57 @@ fn next_deck_state(mut key_deck: Cards) -> Cards {
         .unwrap();
     bottom.append(top);
     bottom.append(Cards(vec![bottom_card]));
-    bottom.clone()
+    bottom
+}
+
+// Find the output card, or Joker, of a deck that has been through the deck changing steps
+fn output_card(key_deck: &Cards) -> OutputCard {
+    let top_card_value = &key_deck
+        // can panic if code broken - deck should always have a top card.
+        .look_at(0)
+        .unwrap()
+        .value();
+    // hidden canceling adjustments: top_card_value [1..53] so subtract 1 to make it
+    // an index range of [0..52] (i.e. so original 1 is pointing to first card)
+    // and then add 1 to look at card *after* the one indexed
+    // by the top card value for a net adjustment of 0
+    let output_card_candidate_position = card_val_into_position(top_card_value);
+    // can panic if code broken - output card should always be present
+    output_card_at(key_deck, output_card_candidate_position.into()).unwrap()
+}
+
+// The card at a given position as an output card, None if the position is beyond the deck
+fn output_card_at(key_deck: &Cards, position: usize) -> Option<OutputCard> {
+    let card = *key_deck.look_at(position).ok()?;
+    let value = card.value();
+    let letter = match card {
+        Card::Joker(_) => None,
+        _ => Some(value_into_letter(&card_val_into_let_val(value))),
+    };
+    Some(OutputCard {
+        card,
+        value,
+        letter,
+    })
+}
+
+/// Options controlling how a key deck is created from a Passphrase
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyingOptions {
+    /// Perform Schneier's "Optional step" after keying: the jokers are removed and then
+    /// Joker A is placed after the card counted down to by the value of the next to last
+    /// passphrase letter, followed by Joker B placed after the card counted down to by the value
+    /// of the last passphrase letter.  Ignored for passphrases shorter than two letters.
+    ///
+    /// Counting down for Joker B counts Joker A, already in place, as a card, which matters
+    /// whenever the last letter's value is at least that of the next to last.  This reading has
+    /// not been checked against Schneier's text or vectors from another implementation, so a
+    /// deck keyed elsewhere with such a passphrase may place Joker B one card further down.
+    pub optional_step: bool,
 }
 
 /// Create a key deck from a Passphrase (aka key).  This follows the basic algorithm in that it does
-/// not include the "Optional step".
+/// not include the "Optional step" (see key_deck_from_passphrase_with_options()).
 ///
 /// # Examples
 /// ```
@@ -376,27 +925,305 @@ fn next_deck_state(mut key_deck: Cards) -> Cards {
 /// ```
 ///
 pub fn key_deck_from_passphrase(passphrase: &Passphrase) -> Cards {
+    key_deck_from_passphrase_with_options(passphrase, &KeyingOptions::default())
+}
+
+/// Create a key deck from a Passphrase (aka key) with the given KeyingOptions, e.g. to include
+/// the "Optional step" which uses the last two passphrase letters to place the jokers.
+///
+/// # Examples
+/// ```
+/// use std::str::FromStr;
+/// use card_play::{Card, JokerId};
+/// use solitaire_cypher::{key_deck_from_passphrase_with_options, KeyingOptions, Passphrase};
+/// let passphrase = Passphrase::from_str("cryptonomicon").unwrap();
+/// let options = KeyingOptions { optional_step: true };
+/// let keyed_deck = key_deck_from_passphrase_with_options(&passphrase, &options);
+/// // next to last letter O is 15, last letter N is 14 (inserting B in front of A moves A down)
+/// assert_eq!(keyed_deck.find(Card::Joker(JokerId::A)), Some(16));
+/// assert_eq!(keyed_deck.find(Card::Joker(JokerId::B)), Some(14));
+/// ```
+pub fn key_deck_from_passphrase_with_options(
+    passphrase: &Passphrase,
+    options: &KeyingOptions,
+) -> Cards {
+    key_deck_from_passphrase_with(passphrase, options, &mut |_, _, _| {})
+}
+
+// Key a deck calling observer with the (1 based) passphrase letter number, the sub-step and the
+// deck after each sub-step (see trace module)
+fn key_deck_from_passphrase_with(
+    passphrase: &Passphrase,
+    options: &KeyingOptions,
+    observer: &mut impl FnMut(usize, SubStep, &Cards),
+) -> Cards {
     // can panic if code broken - next line uses illegal joker count
-    let mut deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+    let deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+    let mut deck = key_deck_with_letters(deck, &passphrase.0, observer);
+
+    if options.optional_step && passphrase.len() >= 2 {
+        let next_to_last = letter_into_value(&passphrase.0[passphrase.len() - 2]);
+        let last = letter_into_value(&passphrase.0[passphrase.len() - 1]);
+        deck.0.retain(|card| !matches!(card, Card::Joker(_)));
+        // letter values are at most 26 so always within the (joker-less) deck
+        deck.0
+            .insert(usize::from(next_to_last), Card::Joker(JokerId::A));
+        deck.0.insert(usize::from(last), Card::Joker(JokerId::B));
+        observer(passphrase.len(), SubStep::OptionalStep, &deck);
+    }
+    deck
+}
 
-    for letter in passphrase.iter() {
-        deck = next_deck_state(deck);
+// Key a deck with each letter in turn: a Solitaire step followed by a count cut at the letter
+// value, calling observer as key_deck_from_passphrase_with() does
+fn key_deck_with_letters(
+    mut deck: Cards,
+    letters: &[UpperLetter],
+    observer: &mut impl FnMut(usize, SubStep, &Cards),
+) -> Cards {
+    for (i, letter) in letters.iter().enumerate() {
+        deck = next_deck_state_with(deck, &mut |sub_step, deck| observer(i + 1, sub_step, deck));
 
-        // count cut at passphrase letter value, maintain bottom card
+        // count cut at letter value, maintain bottom card
         let letter_value = letter_into_value(letter);
-        let TwoStacks(top, mut bottom) = deck.clone().cut(letter_value.into());
-        let bottom_card = bottom
-            .0
-            // can panic if code broken - bottom should always have a bottom card
-            .pop()
-            .unwrap();
-        bottom.append(top);
-        bottom.append(Cards(vec![bottom_card]));
-        deck = bottom.clone();
+        deck = count_cut(deck, letter_value.into());
+        observer(i + 1, SubStep::PassphraseCut(*letter), &deck);
     }
     deck
 }
 
+/// Check that a key deck is exactly one of each of the 52 cards and the two jokers (FA and FB),
+/// in any order, as required of a pre-arranged key deck
+///
+/// returns Err CypherError::IncompleteDeck naming the missing and duplicate cards otherwise
+///
+/// # Examples
+/// ```
+/// use std::str::FromStr;
+/// use card_play::{Card, Cards, JokerId, JokersPerDeck, Suit};
+/// use solitaire_cypher::{validate_key_deck, CypherError};
+/// let mut deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+/// assert!(validate_key_deck(&deck).is_ok());
+/// deck.0[0] = Card::Joker(JokerId::B);
+/// assert_eq!(
+///     validate_key_deck(&deck),
+///     Err(CypherError::IncompleteDeck {
+///         missing: vec![Card::Ace(Suit::Heart)],
+///         duplicate: vec![Card::Joker(JokerId::B)],
+///     })
+/// );
+/// ```
+pub fn validate_key_deck(deck: &Cards) -> Result<(), CypherError> {
+    // can panic if code broken - next line uses illegal joker count
+    let new_deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+    let mut missing = Vec::new();
+    let mut duplicate = Vec::new();
+    for card in new_deck.0.iter() {
+        match deck.0.iter().filter(|c| *c == card).count() {
+            0 => missing.push(*card),
+            1 => {}
+            _ => duplicate.push(*card),
+        }
+    }
+    if missing.is_empty() && duplicate.is_empty() {
+        Ok(())
+    } else {
+        Err(CypherError::IncompleteDeck { missing, duplicate })
+    }
+}
+
+/// Create a key deck from card names (as displayed by card_play::Cards, e.g. "AH 2H .. FA FB") or
+/// numbers (card_play default values 1-54, i.e. new deck order with FA 53 and FB 54) separated by
+/// any whitespace, including line breaks, and check it with validate_key_deck()
+///
+/// returns Err CypherError::UnknownCard for a name which isn't a card or
+/// CypherError::IncompleteDeck if the cards aren't a complete deck
+///
+/// # Examples
+/// ```
+/// use card_play::{Cards, JokersPerDeck};
+/// use solitaire_cypher::{key_deck_from_str, CypherError};
+/// let new_deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+/// let names = new_deck.to_string().replace(" FA", "\n FA");
+/// assert_eq!(key_deck_from_str(&names), Ok(new_deck.clone()));
+/// let numbers: Vec<String> = (1..=54).map(|v| v.to_string()).collect();
+/// assert_eq!(key_deck_from_str(&numbers.join(" ")), Ok(new_deck));
+/// assert_eq!(
+///     key_deck_from_str("AH 2H XX").unwrap_err(),
+///     CypherError::UnknownCard { position: 2, name: "XX".to_string() }
+/// );
+/// ```
+pub fn key_deck_from_str(s: &str) -> Result<Cards, CypherError> {
+    // can panic if code broken - next line uses illegal joker count
+    let new_deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+    let mut deck = Cards(Vec::new());
+    for (position, name) in s.split_whitespace().enumerate() {
+        let by_number = name
+            .parse::<usize>()
+            .ok()
+            .and_then(|v| v.checked_sub(1))
+            .and_then(|i| new_deck.0.get(i).copied());
+        match by_number.map_or_else(|| Card::from_str(name), Ok) {
+            Ok(card) => deck.0.push(card),
+            Err(_) => {
+                return Err(CypherError::UnknownCard {
+                    position,
+                    name: name.to_string(),
+                })
+            }
+        }
+    }
+    validate_key_deck(&deck)?;
+    Ok(deck)
+}
+
+/// The output card found by one complete step of the Solitaire algorithm along with its value and
+/// the resulting KeyStream letter.  letter is None when the output card is a joker, in which
+/// case the step produces no KeyStream letter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputCard {
+    /// The output card
+    pub card: Card,
+    /// Solitaire value of the output card (1-53, i.e. the values listed as "Output:" in the
+    /// published test vectors)
+    pub value: CardValue,
+    /// The KeyStream letter produced or None if the output card is a joker
+    pub letter: Option<UpperLetter>,
+}
+
+/// Stateful keystream generator.  Owns the deck and produces KeyStream letters lazily, one
+/// letter at a time, so text of unknown length can be encrypted or decrypted in chunks.  The
+/// deck state between letters is available via deck().  As an Iterator it never ends.
+///
+/// Examples
+/// ```
+/// use std::str::FromStr;
+/// use solitaire_cypher::{encrypt, key_deck_from_passphrase, KeystreamGenerator, Passphrase, PlainText};
+/// let passphrase = Passphrase::from_str("cryptonomicon").unwrap();
+/// let mut generator = KeystreamGenerator::new(key_deck_from_passphrase(&passphrase));
+/// let mut ct = String::new();
+/// for chunk in ["SOLIT", "AIREX"] {
+///     let pt = PlainText::from_str(chunk).unwrap();
+///     let ks = generator.key_stream(pt.len());
+///     ct.push_str(&encrypt(&pt, &ks).to_string());
+/// }
+/// assert_eq!(ct, "KIRAKSFJAN");
+/// ```
+#[derive(Debug, Clone)]
+pub struct KeystreamGenerator {
+    deck: Cards,
+}
+
+impl KeystreamGenerator {
+    /// Create a generator which will step the given key deck to produce its letters
+    pub fn new(key_deck: Cards) -> KeystreamGenerator {
+        KeystreamGenerator { deck: key_deck }
+    }
+
+    /// The current deck state, i.e. that following the output of the last letter produced
+    ///
+    /// # Examples
+    /// ```
+    /// use card_play::{Cards, JokersPerDeck};
+    /// use solitaire_cypher::KeystreamGenerator;
+    /// let deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+    /// let mut generator = KeystreamGenerator::new(deck.clone());
+    /// assert_eq!(*generator.deck(), deck);
+    /// generator.next();
+    /// assert_ne!(*generator.deck(), deck);
+    /// ```
+    pub fn deck(&self) -> &Cards {
+        &self.deck
+    }
+
+    /// Consume the generator returning the current deck state
+    pub fn into_deck(self) -> Cards {
+        self.deck
+    }
+
+    /// Produce a KeyStream of exactly the specified length (no rounding up to a multiple of 5)
+    /// continuing from where the last letter left off
+    ///
+    /// # Examples
+    /// ```
+    /// use card_play::{Cards, JokersPerDeck};
+    /// use solitaire_cypher::KeystreamGenerator;
+    /// let deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+    /// let mut generator = KeystreamGenerator::new(deck);
+    /// assert_eq!(generator.key_stream(3).to_string(), "DWJ");
+    /// assert_eq!(generator.key_stream(4).to_string(), "XHYR");
+    /// ```
+    pub fn key_stream(&mut self, len: usize) -> KeyStream {
+        KeyStream(self.take(len).collect())
+    }
+
+    /// Perform one complete step of the Solitaire algorithm returning the output card, which may
+    /// be a joker and so not produce a KeyStream letter
+    ///
+    /// # Examples
+    /// ```
+    /// use card_play::{Cards, JokersPerDeck};
+    /// use solitaire_cypher::KeystreamGenerator;
+    /// let deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+    /// let mut generator = KeystreamGenerator::new(deck);
+    /// let values: Vec<u8> = (0..5).map(|_| u8::from(generator.next_output().value)).collect();
+    /// assert_eq!(values, vec![4, 49, 10, 53, 24]);
+    /// ```
+    pub fn next_output(&mut self) -> OutputCard {
+        self.next_output_with(&mut |_, _| {})
+    }
+
+    // next_output() calling observer with the deck after each sub-step (see trace module)
+    fn next_output_with(&mut self, observer: &mut impl FnMut(SubStep, &Cards)) -> OutputCard {
+        self.deck = next_deck_state_with(std::mem::take(&mut self.deck), observer);
+        let output = output_card(&self.deck);
+        observer(SubStep::Output(output), &self.deck);
+        output
+    }
+}
+
+impl Iterator for KeystreamGenerator {
+    type Item = UpperLetter;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(letter) = self.next_output().letter {
+                return Some(letter);
+            }
+        }
+    }
+}
+
+/// Obtain the output cards, including skipped jokers, for the given number of KeyStream letters
+/// from a Card deck.  Unlike get_key_stream() the letter count is not rounded up to a multiple of
+/// five.  The values of the returned cards are those listed as "Output:" in the published test
+/// vectors.
+///
+/// Examples
+/// ```
+/// use card_play::{Card, Cards, JokerId, JokersPerDeck};
+/// use solitaire_cypher::get_output_cards;
+/// let deck = Cards::new(1, JokersPerDeck::new(2).unwrap()); // un-keyed deck example
+/// let outputs = get_output_cards(deck, 5);
+/// let values: Vec<u8> = outputs.iter().map(|o| u8::from(o.value)).collect();
+/// assert_eq!(values, vec![4, 49, 10, 53, 24, 8]);
+/// assert!(matches!(outputs[3].card, Card::Joker(_)));
+/// assert_eq!(outputs[3].letter, None);
+/// ```
+pub fn get_output_cards(key_deck: Cards, key_length: usize) -> Vec<OutputCard> {
+    let mut generator = KeystreamGenerator::new(key_deck);
+    let mut outputs = Vec::new();
+    let mut letter_count = 0;
+    while letter_count < key_length {
+        let output = generator.next_output();
+        if output.letter.is_some() {
+            letter_count += 1;
+        }
+        outputs.push(output);
+    }
+    outputs
+}
+
 /// Create a KeyStream of the specified length from a Card deck
 ///
 /// Examples
@@ -408,42 +1235,14 @@ pub fn key_deck_from_passphrase(passphrase: &Passphrase) -> Cards {
 /// let ks = get_key_stream(deck, len);
 /// ```
 pub fn get_key_stream(key_deck: Cards, key_length: usize) -> KeyStream {
-    let mut key_deck = key_deck;
-    let mut key_stream = KeyStream::new();
     // Ensure key length is a multiple of 5 (as is tradition) so the cypher text will be also
-    let key_length = ((key_length + 4) / 5) * 5; // integer divide to drop remainder
-    while key_stream.0.len() < key_length {
-        key_deck = next_deck_state(key_deck);
-
-        // Find output card, or Joker
-        let top_card_value = &key_deck
-            // can panic if code broken - deck should always have a top card.
-            .look_at(0)
-            .unwrap()
-            .value();
-        // hidden canceling adjustments: top_card_value [1..53] so subtract 1 to make it
-        // an index range of [0..52] (i.e. so original 1 is pointing to first card)
-        // and then add 1 to look at card *after* the one indexed
-        // by the top card value for a net adjustment of 0
-        let output_card_candidate_position = card_val_into_position(top_card_value);
-        let output_card_candidate = &key_deck
-            // can panic if code broken - output card should always be present
-            .look_at(output_card_candidate_position.into())
-            .unwrap();
-        if **output_card_candidate != Card::Joker(JokerId::A)
-            && **output_card_candidate != Card::Joker(JokerId::B)
-        {
-            key_stream.0.push(value_into_letter(&card_val_into_let_val(
-                (*output_card_candidate).value(),
-            )));
-        }
-    }
-    key_stream
+    let key_length = key_length.div_ceil(5) * 5;
+    KeystreamGenerator::new(key_deck).key_stream(key_length)
 }
 
 /// Encrypt PlainText into CypherText using the given KeyStream.
-/// Will panic if KeyStream length is less than PlainText length rounded
-/// up to the nearest multiple of 5.
+/// Will panic if KeyStream length is less than PlainText length (see try_encrypt() for the
+/// non-panicking version).
 ///
 /// Examples
 /// ```
@@ -455,11 +1254,33 @@ pub fn get_key_stream(key_deck: Cards, key_length: usize) -> KeyStream {
 /// assert_eq!(ct.to_string(), "OSKJJ JGTMW");
 /// ```
 pub fn encrypt(pt: &PlainText, ks: &KeyStream) -> CypherText {
+    match try_encrypt(pt, ks) {
+        Ok(ct) => ct,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+/// Encrypt PlainText into CypherText using the given KeyStream.
+/// Returns Err CypherError::KeyStreamTooShort if the KeyStream length is less than the PlainText
+/// length.
+///
+/// Examples
+/// ```
+/// use std::str::FromStr;
+/// use solitaire_cypher::{try_encrypt, CypherError, KeyStream, PlainText};
+/// let pt = PlainText::from_str("DONOT USEPC").unwrap();
+/// let ks = KeyStream::from_str("KDWUP").unwrap();
+/// let err = try_encrypt(&pt, &ks).unwrap_err();
+/// assert_eq!(err, CypherError::KeyStreamTooShort { needed: 10, available: 5 });
+/// ```
+pub fn try_encrypt(pt: &PlainText, ks: &KeyStream) -> Result<CypherText, CypherError> {
     if pt.0.len() > ks.0.len() {
-        panic!("KeyStream not long enough");
+        return Err(CypherError::KeyStreamTooShort {
+            needed: pt.0.len(),
+            available: ks.0.len(),
+        });
     }
 
-    let pt = (*pt).clone();
     let mut ct: CypherText = CypherText(vec![]);
 
     for (i, p) in pt.0.iter().enumerate() {
@@ -470,10 +1291,12 @@ pub fn encrypt(pt: &PlainText, ks: &KeyStream) -> CypherText {
             &(LetterValue::new(((u8::from(pt_value) + key_value - 1) % 26) + 1).unwrap()),
         ));
     }
-    ct
+    Ok(ct)
 }
 
-/// Decrypt CypherText into PlainText using the given KeyStream
+/// Decrypt CypherText into PlainText using the given KeyStream.
+/// Will panic if KeyStream length is less than CypherText length (see try_decrypt() for the
+/// non-panicking version).
 ///
 /// Examples
 /// ```
@@ -487,8 +1310,35 @@ pub fn encrypt(pt: &PlainText, ks: &KeyStream) -> CypherText {
 /// assert_eq!("SOLITAIREX", recovered_pt.to_string());
 /// ```
 pub fn decrypt(ct: &CypherText, ks: &KeyStream) -> PlainText {
+    match try_decrypt(ct, ks) {
+        Ok(pt) => pt,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+/// Decrypt CypherText into PlainText using the given KeyStream.
+/// Returns Err CypherError::KeyStreamTooShort if the KeyStream length is less than the
+/// CypherText length.
+///
+/// Examples
+/// ```
+/// use std::str::FromStr;
+/// use solitaire_cypher::{try_decrypt, CypherError, CypherText, KeyStream};
+/// let ct = CypherText::from_str("KIRAK SFJAN").unwrap();
+/// let ks = KeyStream::from_str("KDWUP ONOW").unwrap();
+/// match try_decrypt(&ct, &ks) {
+///     Err(CypherError::KeyStreamTooShort { needed, available }) => {
+///         assert_eq!((needed, available), (10, 9))
+///     }
+///     _ => panic!("expected a KeyStreamTooShort error"),
+/// }
+/// ```
+pub fn try_decrypt(ct: &CypherText, ks: &KeyStream) -> Result<PlainText, CypherError> {
     if ct.0.len() > ks.0.len() {
-        panic!("KeyStream not long enough");
+        return Err(CypherError::KeyStreamTooShort {
+            needed: ct.0.len(),
+            available: ks.0.len(),
+        });
     }
 
     let mut pt: PlainText = PlainText(vec![]);
@@ -502,20 +1352,12 @@ pub fn decrypt(ct: &CypherText, ks: &KeyStream) -> PlainText {
             .unwrap()),
         ));
     }
-    pt
-}
-
-fn remove_ticks(s: &str) -> String {
-    s.chars().filter(|c| *c != '\'').collect()
+    Ok(pt)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use regex::Regex;
-    use std::fs::File;
-    use std::io::{self, BufRead};
-    use std::path::Path;
 
     #[test]
     // Open the file of test vectors "sol-test.txt" and run all the enclosed tests
@@ -530,53 +1372,231 @@ mod tests {
     // Test vectors obtained from: https://www.schneier.com/academic/solitaire/ as
     // referenced from https://en.wikipedia.org/wiki/Solitaire_(cipher)
     fn test_vectors_from_wiki() {
-        let pt_re = Regex::new(r"^Plaintext: +([A-Z]+) *$").unwrap();
-        let ct_re = Regex::new(r"^Ciphertext: +((([A-Z]{5}+) *)+) *$").unwrap();
-        let key_re = Regex::new(r"^Key: +('([a-z]+)'|(<null key>)) *$").unwrap();
-        let lines = read_lines("./sol-test.txt");
-        let lines = lines.expect("failed to open file");
-        let mut pt: PlainText = PlainText::new();
-        let mut pp: Passphrase = Passphrase::from_str("").unwrap();
-        let mut ct: CypherText;
-        let mut key_deck: Cards;
-        let mut ks: KeyStream;
-        let mut got_one = false;
-
-        for line in lines {
-            let line = line.expect("could not read line");
-            if let Some(pt_str) = pt_re.captures(&line) {
-                pt = PlainText::from_str(&pt_str[1]).unwrap();
-            } else if let Some(pp_str) = key_re.captures(&line) {
-                if let Ok(ppp) = Passphrase::from_str(&pp_str[1]) {
-                    pp = ppp;
-                } else {
-                    pp = Passphrase::from_str("").unwrap();
+        run_vector_file("./sol-test.txt", &KeyingOptions::default());
+    }
+
+    #[test]
+    // Regression fixtures in the format of "sol-test.txt" keyed with the "Optional step".  These
+    // were generated by this crate, as no published vectors for the optional step were to hand,
+    // so they only guard against changes to its behaviour (including its reading of Joker B's
+    // placement, see KeyingOptions) and don't validate it.
+    fn test_optional_step_regression() {
+        run_vector_file(
+            "./optional-step-regression.txt",
+            &KeyingOptions {
+                optional_step: true,
+            },
+        );
+    }
+
+    #[test]
+    fn test_optional_step_joker_placement() {
+        let pp = Passphrase::from_str("fo").unwrap();
+        let basic = key_deck_from_passphrase(&pp);
+        let optional = key_deck_from_passphrase_with_options(
+            &pp,
+            &KeyingOptions {
+                optional_step: true,
+            },
+        );
+        // F is 6 and O is 15 so A goes after the 6th card and B after the 15th, A counting as
+        // one of the 15 so only 14 plain cards are above B (this crate's reading, see
+        // KeyingOptions)
+        assert_eq!(optional.find(Card::Joker(JokerId::A)), Some(6));
+        assert_eq!(optional.find(Card::Joker(JokerId::B)), Some(15));
+        // O and F the other way round: B goes in above A so A is pushed down one
+        let reversed = key_deck_from_passphrase_with_options(
+            &Passphrase::from_str("of").unwrap(),
+            &KeyingOptions {
+                optional_step: true,
+            },
+        );
+        assert_eq!(reversed.find(Card::Joker(JokerId::A)), Some(16));
+        assert_eq!(reversed.find(Card::Joker(JokerId::B)), Some(6));
+        let without_jokers = |deck: &Cards| -> Vec<Card> {
+            deck.0
+                .iter()
+                .filter(|card| !matches!(card, Card::Joker(_)))
+                .copied()
+                .collect()
+        };
+        assert_eq!(without_jokers(&basic), without_jokers(&optional));
+
+        // too short for the optional step
+        let pp = Passphrase::from_str("f").unwrap();
+        assert_eq!(
+            key_deck_from_passphrase(&pp),
+            key_deck_from_passphrase_with_options(
+                &pp,
+                &KeyingOptions {
+                    optional_step: true
                 }
-            } else if let Some(ct_str) = ct_re.captures(&line) {
-                ct = CypherText::from_str(&ct_str[1]).unwrap();
+            )
+        );
+    }
 
-                key_deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
-                if !pp.is_empty() {
-                    key_deck = key_deck_from_passphrase(&pp);
+    fn run_vector_file(filename: &str, options: &KeyingOptions) {
+        let vectors = std::fs::read_to_string(filename).expect("failed to open file");
+        let results = vectors::run_test_vectors(&vectors, options).unwrap();
+        assert!(!results.is_empty(), "failed to run any test vectors");
+        for result in results {
+            assert!(result.passed(), "{}", result);
+        }
+    }
+
+    #[test]
+    fn test_generator_chunks_match_key_stream() {
+        let pp = Passphrase::from_str("cryptonomicon").unwrap();
+        let ks = get_key_stream(key_deck_from_passphrase(&pp), 100);
+        let mut generator = KeystreamGenerator::new(key_deck_from_passphrase(&pp));
+        let mut chunked = KeyStream::new();
+        for len in [1, 7, 0, 30, 62] {
+            chunked.0.append(&mut generator.key_stream(len).0);
+        }
+        assert_eq!(chunked.0, ks.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "KeyStream not long enough: 10 letters needed, 5 available")]
+    fn test_encrypt_short_key_stream_panics() {
+        let pt = PlainText::from_str("SOLITAIRE").unwrap();
+        let ks = KeyStream::from_str("KDWUP").unwrap();
+        encrypt(&pt, &ks);
+    }
+
+    #[test]
+    fn test_strict_parsing() {
+        let err = CypherText::from_str_strict("KIR4K\tSF.AN").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "string contains invalid symbols: '4' at position 3, '.' at position 8"
+        );
+        // lenient parsing skips the same symbols
+        let ct = CypherText::from_str("KIR4K\tSF.AN").unwrap();
+        assert_eq!(ct.to_string(), "KIRKS FAN");
+        assert!(!ct.is_whole_groups());
+        assert!(PlainText::from_str_strict("Café").is_err());
+        assert_eq!(PlainText::from_str_strict("").unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_padding_round_trip() {
+        let x = UpperLetter::new(b'X').unwrap();
+        let q = UpperLetter::new(b'Q').unwrap();
+        for text in [
+            "", "A", "AB", "ABC", "ABCD", "ABCDE", "FOXX", "XXXXXXX", "QEQD",
+        ] {
+            for padding in [Padding::Marked(x), Padding::Marked(q), Padding::None] {
+                let mut pt = PlainText::from_letters(text);
+                pt.pad(padding);
+                if padding != Padding::None {
+                    assert!(pt.len().is_multiple_of(5) && pt.len() > text.len());
                 }
-                ks = get_key_stream(key_deck, pt.0.len());
-                let computed_ct = encrypt(&pt, &ks);
-                let computed_ct_str = computed_ct.to_string();
-                let ct_str = ct.to_string();
-                assert_eq!(computed_ct_str, ct_str);
-                let computed_pt = decrypt(&ct, &ks);
-                let computed_pt_str = computed_pt.to_string();
-                assert_eq!(computed_pt_str, pt.to_string());
-                got_one = true;
+                pt.strip_padding(padding).unwrap();
+                assert_eq!(pt.to_string(), text);
+            }
+        }
+        // traditional padding loses message letters that match the pad letter
+        let mut pt = PlainText::from_str("FOXX").unwrap();
+        pt.strip_padding(Padding::Letter(x)).unwrap();
+        assert_eq!(pt.to_string(), "FO");
+        for bad in ["", "ABCDF", "ABCQD", "AE"] {
+            let mut pt = PlainText::from_letters(bad);
+            assert_eq!(
+                pt.strip_padding(Padding::Marked(x)),
+                Err(CypherError::BadPadding)
+            );
+        }
+    }
+
+    #[test]
+    fn test_key_deck_errors() {
+        let new_deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+        let mut short = new_deck.clone();
+        short.0.truncate(50);
+        assert_eq!(
+            validate_key_deck(&short).unwrap_err().to_string(),
+            "key deck is not a complete 54 card deck: missing 2S AS FA FB"
+        );
+        let mut long = new_deck.clone();
+        long.0.push(Card::Joker(JokerId::A));
+        long.0.push(Card::King(Suit::Club));
+        assert_eq!(
+            validate_key_deck(&long).unwrap_err().to_string(),
+            "key deck is not a complete 54 card deck: duplicate KC FA"
+        );
+        let mut swapped = new_deck.clone();
+        swapped.0.swap(0, 53);
+        assert_eq!(key_deck_from_str(&swapped.to_string()), Ok(swapped));
+        assert_eq!(
+            key_deck_from_str("").unwrap_err(),
+            CypherError::IncompleteDeck {
+                missing: new_deck.0.clone(),
+                duplicate: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn test_passphrase_non_letter_position() {
+        assert_eq!(
+            Passphrase::from_str("'cryp%omicon'").unwrap_err(),
+            CypherError::NonLetter {
+                position: 5,
+                character: '%'
+            }
+        );
+        assert_eq!(
+            Passphrase::from_str("'bcd'").unwrap().to_string(),
+            "BCD".to_string()
+        );
+    }
+
+    #[test]
+    fn test_passphrase_allocated_once() {
+        // spellings longer than the UTF-8 they replace still fit the first allocation
+        let pp = Passphrase::from_str("ⅷⅷ'ﬃ'").unwrap();
+        assert_eq!(pp.to_string(), "VIIIVIIIFFI");
+        assert_eq!(pp.0.capacity(), pp.len());
+        assert!(pp.len() > "ⅷⅷﬃ".len());
+    }
+
+    #[test]
+    fn test_previous_deck_state_undoes_next() {
+        const ITER_COUNT: usize = 1000;
+        let mut ambiguous = 0;
+        for _ in 0..ITER_COUNT {
+            let mut deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+            deck.shuffle_fy();
+            let next = next_deck_state(deck.clone());
+            let previous = previous_deck_state(&next);
+            assert!(previous.contains(&deck), "original deck not recovered");
+            for candidate in previous.iter() {
+                assert_eq!(next_deck_state(candidate.clone()), next);
+            }
+            if previous.len() > 1 {
+                ambiguous += 1;
             }
         }
-        assert!(got_one, "failed to run any test vectors");
-    }
-    fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
-    where
-        P: AsRef<Path>,
-    {
-        let file = File::open(filename)?;
-        Ok(io::BufReader::new(file).lines())
+        // jokers wrap around the bottom only occasionally
+        assert!(ambiguous < ITER_COUNT / 10);
+    }
+
+    #[test]
+    fn test_previous_deck_state_ambiguous() {
+        // Joker A on the bottom wraps to below the top card, as it does from the top card
+        let wrapped = Cards::from_str(
+            "AH FB 2H 3H 4H 5H 6H 7H 8H 9H TH JH QH KH AC 2C 3C 4C 5C 6C 7C 8C 9C TC JC QC KC \
+             KD QD JD TD 9D 8D 7D 6D 5D 4D 3D 2D AD KS QS JS TS 9S 8S 7S 6S 5S 4S 3S 2S AS FA",
+        )
+        .unwrap();
+        let mut direct = wrapped.clone();
+        let fa = direct.0.pop().unwrap();
+        direct.0.insert(0, fa);
+        let next = next_deck_state(wrapped.clone());
+        assert_eq!(next, next_deck_state(direct.clone()));
+        let previous = previous_deck_state(&next);
+        assert_eq!(previous.len(), 2);
+        assert!(previous.contains(&wrapped) && previous.contains(&direct));
     }
 }
@@ -0,0 +1,496 @@
+//! # Recovery
+//!
+//! A teaching aid showing why known plaintext matters.  Solitaire is a stream cypher so anyone
+//! holding a PlainText and its CypherText has the KeyStream (see known_key_stream()), and every
+//! KeyStream letter narrows down the key deck: the output card giving letter L must be card L or
+//! card L + 26 (see output_constraints()).
+//!
+//! With 54! possible key decks this never narrows a full deck down far enough to search, but a
+//! reduced deck (see deck_state module) falls quickly.  Each letter of known KeyStream rejects all
+//! but about 1 in M (the alphabet modulus, or the number of plain cards if fewer) of the candidate
+//! decks, so a handful of letters identifies the deck (along with any deck stepping to it with a
+//! joker as output card, which gives the same KeyStream).  The search only places cards as
+//! stepping needs them and drops a partial deck as soon as an output card breaks its constraint,
+//! and every card already known (e.g. seen over a shoulder) cuts it further (see recover_deck()).
+
+use crate::deck_state::{move_circular, valid_variant, DeckState};
+use crate::{letter_into_value, try_decrypt, CypherText, KeyStream, PlainText, UpperLetter};
+use card_play::{Card, Cards, JokersPerDeck};
+use std::fmt;
+use std::fmt::Display;
+
+/// The KeyStream letters used for the known (start of the) PlainText, i.e. the CypherText minus
+/// the PlainText over the length of the shorter of the two
+///
+/// # Examples
+/// ```
+/// use std::str::FromStr;
+/// use solitaire_cypher::{CypherText, PlainText};
+/// use solitaire_cypher::recovery::known_key_stream;
+/// let pt = PlainText::from_letters("SOLITAIRE");
+/// let ct = CypherText::from_str("KIRAK SFJAN").unwrap();
+/// assert_eq!(known_key_stream(&pt, &ct).to_string(), "RTFRQ RWRV");
+/// ```
+pub fn known_key_stream(pt: &PlainText, ct: &CypherText) -> KeyStream {
+    let len = pt.len().min(ct.len());
+    let difference = try_decrypt(
+        &CypherText(ct.0[..len].to_vec()),
+        &KeyStream(pt.0[..len].to_vec()),
+    );
+    // can panic if code broken - the "KeyStream" is as long as the "CypherText"
+    KeyStream(difference.unwrap().0)
+}
+
+/// The two cards of a full deck which could have been the output card for a KeyStream letter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputConstraint {
+    /// The KeyStream letter
+    pub letter: UpperLetter,
+    /// The output card was one of these (values L and L + 26)
+    pub cards: [Card; 2],
+}
+
+impl Display for OutputConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} or {}",
+            u8::from(self.letter) as char,
+            self.cards[0],
+            self.cards[1]
+        )
+    }
+}
+
+/// The output card candidates for each letter of the KeyStream (see OutputConstraint)
+///
+/// # Examples
+/// ```
+/// use std::str::FromStr;
+/// use solitaire_cypher::KeyStream;
+/// use solitaire_cypher::recovery::output_constraints;
+/// let ks = KeyStream::from_str("RTF").unwrap();
+/// let constraints: Vec<String> = output_constraints(&ks).iter().map(|c| c.to_string()).collect();
+/// assert_eq!(constraints, vec!["R: 5C or 9S", "T: 7C or 7S", "F: 6H or 8D"]);
+/// ```
+pub fn output_constraints(ks: &KeyStream) -> Vec<OutputConstraint> {
+    // can panic if code broken - next line uses illegal joker count
+    let new_deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+    ks.0.iter()
+        .map(|letter| {
+            let index = usize::from(u8::from(*letter) - b'A');
+            OutputConstraint {
+                letter: *letter,
+                cards: [new_deck.0[index], new_deck.0[index + 26]],
+            }
+        })
+        .collect()
+}
+
+/// The result of a reduced deck search
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Recovery<const N: usize = 54, const M: u8 = 26> {
+    /// The decks found giving the KeyStream
+    pub decks: Vec<DeckState<N, M>>,
+    /// Number of partial decks the KeyStream was followed on (see recover_deck())
+    pub tried: u64,
+}
+
+impl<const N: usize, const M: u8> Display for Recovery<N, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} deck(s) found from {} tried",
+            self.decks.len(),
+            self.tried
+        )?;
+        for deck in self.decks.iter() {
+            writeln!(f, "{}", deck)?;
+        }
+        Ok(())
+    }
+}
+
+/// Search for the reduced key decks of N cards, with KeyStream letters modulo M (see
+/// deck_state module), giving the known KeyStream.  known has an entry for every position in the
+/// deck, Some value where the card is already known and None where it isn't.
+///
+/// Rather than trying every arrangement of the unknown cards the KeyStream is followed on the
+/// partially filled deck, a card being placed only once stepping needs it: a joker before it
+/// moves, the bottom card before the count cut, the top card before it is counted and the output
+/// card before its letter is compared.  The output card for the letter L must be card L, L + M,
+/// L + 2M ... (L or L + 26 in a full deck, see output_constraints()) or a joker, so only those
+/// cards are tried there, and a partial deck is dropped as soon as its KeyStream differs.  Cards
+/// the known KeyStream never reaches are left free and every arrangement of them is a deck found.
+/// The search stops once max_decks decks are found.  Decks stepping to the key deck with a joker
+/// as output card give the same KeyStream so are found too unless ruled out by the known cards.
+///
+/// The work done is the number of partial decks tried (Recovery::tried).  Nothing rules out
+/// checking all N! arrangements of the deck, but as each step only needs a few cards placed, and
+/// the output card is all but fixed by its letter, the known KeyStream usually cuts the search to
+/// a small fraction of them.
+///
+/// None unless N and M are in range (see DeckState::from_values()) and the known values are in
+/// range and unique
+///
+/// # Examples
+/// ```
+/// use solitaire_cypher::deck_state::DeckState;
+/// use solitaire_cypher::recovery::recover_deck;
+/// let key_deck = DeckState::<9, 26>::from_values([7, 2, 9, 4, 1, 6, 3, 8, 5]).unwrap();
+/// let mut generator = key_deck;
+/// let ks = generator.key_stream(12).unwrap();
+/// // nothing known about the deck, two other decks step to it with a joker as output card
+/// let recovery = recover_deck::<9, 26>(&[None; 9], &ks, 10).unwrap();
+/// assert_eq!(recovery.decks.len(), 3);
+/// assert!(recovery.decks.contains(&key_deck));
+/// // rather than all 362,880 arrangements
+/// assert_eq!(recovery.tried, 41_224);
+/// // three cards seen while the deck was keyed
+/// let mut known = [None; 9];
+/// known[0] = Some(7);
+/// known[3] = Some(4);
+/// known[8] = Some(5);
+/// let recovery = recover_deck::<9, 26>(&known, &ks, 10).unwrap();
+/// assert_eq!(recovery.decks, vec![key_deck]);
+/// assert_eq!(recovery.tried, 299);
+/// ```
+pub fn recover_deck<const N: usize, const M: u8>(
+    known: &[Option<u8>; N],
+    ks: &KeyStream,
+    max_decks: usize,
+) -> Option<Recovery<N, M>> {
+    if !valid_variant(N, M) {
+        return None;
+    }
+    let mut seen = [false; N];
+    for value in known.iter().flatten() {
+        let index = usize::from(*value).wrapping_sub(1);
+        if index >= N || seen[index] {
+            return None;
+        }
+        seen[index] = true;
+    }
+    let mut remaining: Vec<u8> = (1..=N as u8)
+        .filter(|v| !seen[usize::from(*v) - 1])
+        .collect();
+    let mut search = Search {
+        deck: known.map(|v| v.unwrap_or(0)),
+        ks: &ks.0,
+        max_decks,
+        recovery: Recovery::default(),
+    };
+    search.fill(&mut remaining);
+    Some(search.recovery)
+}
+
+// Depth first search placing the unknown cards as following the KeyStream needs them, with 0 in
+// the deck for a card not yet placed
+struct Search<'a, const N: usize, const M: u8> {
+    deck: [u8; N],
+    ks: &'a [UpperLetter],
+    max_decks: usize,
+    recovery: Recovery<N, M>,
+}
+
+impl<const N: usize, const M: u8> Search<'_, N, M> {
+    // Follow the KeyStream on the partial deck placing each remaining card in turn where needed,
+    // returning false once enough decks are found
+    fn fill(&mut self, remaining: &mut Vec<u8>) -> bool {
+        if self.recovery.decks.len() >= self.max_decks {
+            return false;
+        }
+        self.recovery.tried += 1;
+        match follow(&self.deck, M, self.ks) {
+            Follow::Differs => true,
+            Follow::Gives => self.arrange(remaining),
+            Follow::NeedsCard(card) => {
+                // can panic if code broken - a card not in the deck is always remaining
+                let i = remaining.iter().position(|c| *c == card).unwrap();
+                remaining.swap_remove(i);
+                let mut carry_on = true;
+                for position in 0..N {
+                    if self.deck[position] == 0 && carry_on {
+                        self.deck[position] = card;
+                        carry_on = self.fill(remaining);
+                        self.deck[position] = 0;
+                    }
+                }
+                remaining.push(card);
+                let last = remaining.len() - 1;
+                remaining.swap(i, last);
+                carry_on
+            }
+            Follow::NeedsPosition(position, letter) => {
+                let joker_a = N as u8 - 1;
+                for i in 0..remaining.len() {
+                    let card = remaining[i];
+                    if let Some(letter) = letter {
+                        if card < joker_a && !gives_letter(card, M, letter) {
+                            continue;
+                        }
+                    }
+                    remaining.swap_remove(i);
+                    self.deck[position] = card;
+                    let carry_on = self.fill(remaining);
+                    self.deck[position] = 0;
+                    remaining.push(card);
+                    let last = remaining.len() - 1;
+                    remaining.swap(i, last);
+                    if !carry_on {
+                        return false;
+                    }
+                }
+                true
+            }
+        }
+    }
+
+    // Every arrangement of the remaining cards over the positions still free gives the KeyStream,
+    // returning false once enough decks are found
+    fn arrange(&mut self, remaining: &mut Vec<u8>) -> bool {
+        if self.recovery.decks.len() >= self.max_decks {
+            return false;
+        }
+        let Some(position) = self.deck.iter().position(|v| *v == 0) else {
+            // can panic if code broken - every card has been placed exactly once
+            self.recovery
+                .decks
+                .push(DeckState::from_values(self.deck).unwrap());
+            return true;
+        };
+        for i in 0..remaining.len() {
+            let card = remaining.swap_remove(i);
+            self.deck[position] = card;
+            let carry_on = self.arrange(remaining);
+            self.deck[position] = 0;
+            remaining.push(card);
+            let last = remaining.len() - 1;
+            remaining.swap(i, last);
+            if !carry_on {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// How far the KeyStream can be followed on a partially filled deck
+enum Follow {
+    // The KeyStream differs whatever the cards not yet placed are
+    Differs,
+    // The whole KeyStream is given whatever the cards not yet placed are
+    Gives,
+    // The joker has to be placed to carry on
+    NeedsCard(u8),
+    // The card at the position of the key deck has to be placed to carry on, with the letter it
+    // must give if it is the output card
+    NeedsPosition(usize, Option<UpperLetter>),
+}
+
+// Step the partially filled deck, with 0 for a card not yet placed, for as long as the cards
+// needed are known
+fn follow(deck: &[u8], modulus: u8, ks: &[UpperLetter]) -> Follow {
+    let size = deck.len();
+    let (joker_a, joker_b) = (size as u8 - 1, size as u8);
+    // the position in the key deck of each card of the stepped deck
+    let mut order: Vec<usize> = (0..size).collect();
+    let find = |order: &[usize], card: u8| order.iter().position(|p| deck[*p] == card);
+    let mut letters = ks.iter();
+    let mut expected = letters.next();
+    let mut jokers_in_a_row = 0;
+    while let Some(letter) = expected {
+        if jokers_in_a_row > size {
+            return Follow::Differs;
+        }
+        for (joker, distance) in [(joker_a, 1), (joker_b, 2)] {
+            match find(&order, joker) {
+                Some(i) => move_circular(&mut order, i, distance),
+                None => return Follow::NeedsCard(joker),
+            }
+        }
+
+        // Triple cut swapping the cards above the first joker with those below the second
+        // can panic if code broken - both jokers have been placed
+        let a = find(&order, joker_a).unwrap();
+        let b = find(&order, joker_b).unwrap();
+        let (first, second) = if a < b { (a, b) } else { (b, a) };
+        order.rotate_left(second + 1);
+        order[size - second - 1..].rotate_left(first);
+
+        // Count cut at the value of the bottom card, then the output card found by the top card
+        let count = match deck[order[size - 1]] {
+            0 => return Follow::NeedsPosition(order[size - 1], None),
+            value => usize::from(value.min(joker_a)),
+        };
+        order[..size - 1].rotate_left(count);
+        let count = match deck[order[0]] {
+            0 => return Follow::NeedsPosition(order[0], None),
+            value => usize::from(value.min(joker_a)),
+        };
+        match deck[order[count]] {
+            0 => return Follow::NeedsPosition(order[count], Some(*letter)),
+            output if output >= joker_a => jokers_in_a_row += 1,
+            output if gives_letter(output, modulus, *letter) => {
+                expected = letters.next();
+                jokers_in_a_row = 0;
+            }
+            _ => return Follow::Differs,
+        }
+    }
+    Follow::Gives
+}
+
+// Whether the plain card gives the letter as output card
+fn gives_letter(card: u8, modulus: u8, letter: UpperLetter) -> bool {
+    (card - 1) % modulus + 1 == u8::from(letter_into_value(&letter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{encrypt, get_key_stream, get_output_cards, key_deck_from_passphrase, Passphrase};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_known_plaintext_constraints() {
+        let key_deck = key_deck_from_passphrase(&Passphrase::from_str("cryptonomicon").unwrap());
+        let pt = PlainText::from_str("ATTACK AT DAWN").unwrap();
+        let ks = get_key_stream(key_deck.clone(), pt.len());
+        let ct = encrypt(&pt, &ks);
+        assert_eq!(known_key_stream(&pt, &ct).0, ks.0);
+        // a partial crib gives a partial KeyStream
+        let crib = PlainText::from_letters("ATTACK");
+        assert_eq!(known_key_stream(&crib, &ct).0, ks.0[..6]);
+        for (constraint, output) in output_constraints(&ks)
+            .iter()
+            .zip(get_output_cards(key_deck, pt.len()))
+        {
+            assert!(constraint.cards.contains(&output.card));
+        }
+    }
+
+    #[test]
+    fn test_recover_deck() {
+        let key_deck = DeckState::<8, 26>::from_values([5, 3, 8, 1, 6, 2, 7, 4]).unwrap();
+        let mut generator = key_deck;
+        let ks = generator.key_stream(20).unwrap();
+        // too little KeyStream leaves many candidates
+        let short = KeyStream(ks.0[..2].to_vec());
+        let recovery = recover_deck::<8, 26>(&[None; 8], &short, usize::MAX).unwrap();
+        assert!(recovery.decks.len() > 100);
+        assert!(recovery.decks.contains(&key_deck));
+        // stops at max_decks
+        let recovery = recover_deck::<8, 26>(&[None; 8], &short, 3).unwrap();
+        assert_eq!(recovery.decks.len(), 3);
+        // enough identifies it, along with the deck stepping to it with a joker as output card,
+        // trying far fewer than the 8! arrangements
+        let recovery = recover_deck::<8, 26>(&[None; 8], &ks, usize::MAX).unwrap();
+        assert!(recovery.tried < 40320 / 3);
+        assert_eq!(recovery.decks.len(), 2);
+        assert!(recovery.decks.contains(&key_deck));
+        let mut stepped = key_deck;
+        assert_eq!(stepped.next_output(), None);
+        assert!(recovery.decks.contains(&stepped));
+        // fully known
+        let known = key_deck.values().map(Some);
+        let recovery = recover_deck::<8, 26>(&known, &ks, usize::MAX).unwrap();
+        assert_eq!((recovery.decks.len(), recovery.tried), (1, 1));
+        // no KeyStream, every arrangement
+        let recovery = recover_deck::<6, 26>(&[None; 6], &KeyStream(vec![]), usize::MAX).unwrap();
+        assert_eq!((recovery.decks.len(), recovery.tried), (720, 1));
+        // invalid partial decks
+        assert_eq!(recover_deck::<2, 26>(&[None; 2], &ks, 1), None);
+        assert_eq!(recover_deck::<3, 27>(&[None; 3], &ks, 1), None);
+        assert_eq!(recover_deck::<3, 26>(&[Some(4), None, None], &ks, 1), None);
+        assert_eq!(
+            recover_deck::<3, 26>(&[Some(1), Some(1), None], &ks, 1),
+            None
+        );
+        assert_eq!(recover_deck::<3, 26>(&[Some(0), None, None], &ks, 1), None);
+    }
+
+    #[test]
+    fn test_recover_deck_modulus() {
+        // with only 3 letters each letter says less so more KeyStream is needed
+        let key_deck = DeckState::<8, 3>::from_values([5, 3, 8, 1, 6, 2, 7, 4]).unwrap();
+        let mut generator = key_deck;
+        let ks = generator.key_stream(40).unwrap();
+        assert!(ks.0.iter().all(|l| u8::from(*l) <= b'C'));
+        let short = KeyStream(ks.0[..8].to_vec());
+        let recovery = recover_deck::<8, 3>(&[None; 8], &short, usize::MAX).unwrap();
+        assert!(recovery.decks.len() > 2);
+        let recovery = recover_deck::<8, 3>(&[None; 8], &ks, usize::MAX).unwrap();
+        assert!(recovery.decks.len() <= 2);
+        assert!(recovery.decks.contains(&key_deck));
+    }
+
+    // Every arrangement of the deck checked in full
+    fn exhaustive<const N: usize, const M: u8>(ks: &KeyStream) -> Vec<DeckState<N, M>> {
+        fn arrange<const N: usize, const M: u8>(
+            values: &mut [u8; N],
+            k: usize,
+            ks: &KeyStream,
+            found: &mut Vec<DeckState<N, M>>,
+        ) {
+            if k == N {
+                let deck = DeckState::<N, M>::from_values(*values).unwrap();
+                let mut generator = deck;
+                if generator.key_stream(ks.len()).is_ok_and(|k| k.0 == ks.0) {
+                    found.push(deck);
+                }
+                return;
+            }
+            for i in k..N {
+                values.swap(k, i);
+                arrange(values, k + 1, ks, found);
+                values.swap(k, i);
+            }
+        }
+        let mut found = Vec::new();
+        let mut values = *DeckState::<N, M>::new_deck().values();
+        arrange(&mut values, 0, ks, &mut found);
+        found
+    }
+
+    fn check_against_exhaustive<const N: usize, const M: u8>(values: [u8; N], len: usize) {
+        let key_deck = DeckState::<N, M>::from_values(values).unwrap();
+        let mut generator = key_deck;
+        let ks = generator.key_stream(len).unwrap();
+        let sorted = |decks: Vec<DeckState<N, M>>| {
+            let mut values: Vec<[u8; N]> = decks.iter().map(|d| *d.values()).collect();
+            values.sort();
+            values
+        };
+        let expected = sorted(exhaustive::<N, M>(&ks));
+        let mut known = [None; N];
+        let found = recover_deck::<N, M>(&known, &ks, usize::MAX).unwrap();
+        assert_eq!(sorted(found.decks), expected);
+        // a known card only keeps the decks agreeing with it
+        known[1] = Some(values[1]);
+        let found = recover_deck::<N, M>(&known, &ks, usize::MAX).unwrap();
+        let agreeing: Vec<[u8; N]> = expected.into_iter().filter(|d| d[1] == values[1]).collect();
+        assert_eq!(sorted(found.decks), agreeing);
+    }
+
+    #[test]
+    fn test_recover_deck_matches_exhaustive_search() {
+        check_against_exhaustive::<7, 26>([2, 7, 5, 1, 6, 3, 4], 1);
+        check_against_exhaustive::<7, 26>([2, 7, 5, 1, 6, 3, 4], 4);
+        check_against_exhaustive::<5, 26>([4, 1, 3, 2, 5], 6);
+        check_against_exhaustive::<6, 26>([6, 5, 4, 3, 2, 1], 3);
+        check_against_exhaustive::<7, 2>([3, 6, 1, 7, 2, 5, 4], 5);
+    }
+
+    #[test]
+    fn test_recover_larger_deck() {
+        // 11! is almost 40 million arrangements
+        let key_deck =
+            DeckState::<11, 26>::from_values([9, 2, 11, 5, 7, 1, 10, 4, 6, 3, 8]).unwrap();
+        let mut generator = key_deck;
+        let ks = generator.key_stream(30).unwrap();
+        let recovery = recover_deck::<11, 26>(&[None; 11], &ks, usize::MAX).unwrap();
+        assert!(recovery.decks.contains(&key_deck));
+        assert!(recovery.tried < 1_000_000);
+    }
+}
@@ -0,0 +1,225 @@
+//! # Stats
+//!
+//! Statistics showing the biases of the Solitaire KeyStream.  An ideal KeyStream has every letter,
+//! and every pair of consecutive letters (digram), equally likely.  Solitaire is known to repeat
+//! a letter (i.e. produce the same letter twice in a row) noticeably more often than the
+//! ideal 1 in 26, which is one of the reasons it shouldn't be used for real secrets.
+
+use crate::deck_state::DeckState;
+use crate::{letter_into_value, CypherError, KeyStream};
+use card_play::{Cards, JokersPerDeck};
+use std::fmt;
+use std::fmt::Display;
+
+const LETTERS: usize = 26;
+
+/// Letter, repeated letter and digram statistics of a collection of KeyStreams
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyStreamStats {
+    /// Number of KeyStreams measured
+    pub key_streams: usize,
+    /// Total number of letters measured
+    pub letters: u64,
+    /// Count of each letter A-Z
+    pub letter_counts: [u64; LETTERS],
+    /// Chi-square statistic of the letter counts against a uniform distribution (25 degrees of
+    /// freedom, so around 25 is expected of an ideal KeyStream)
+    pub letter_chi_square: f64,
+    /// Number of consecutive letter pairs (digrams) measured, counted within each KeyStream
+    pub pairs: u64,
+    /// Number of pairs made of the same letter twice
+    pub repeated_pairs: u64,
+    /// Fraction of pairs made of the same letter twice
+    pub repeat_rate: f64,
+    /// Fraction of repeated pairs expected of an ideal KeyStream (1/26)
+    pub expected_repeat_rate: f64,
+    /// Number of standard deviations the repeat rate is from that expected of an ideal KeyStream
+    pub repeat_z_score: f64,
+    /// Chi-square statistic of the digram counts against a uniform distribution (675 degrees
+    /// of freedom, so around 675 is expected of an ideal KeyStream)
+    pub digram_chi_square: f64,
+}
+
+impl KeyStreamStats {
+    /// Measure a collection of KeyStreams
+    ///
+    /// # Examples
+    /// ```
+    /// use std::str::FromStr;
+    /// use solitaire_cypher::KeyStream;
+    /// use solitaire_cypher::stats::KeyStreamStats;
+    /// let key_streams = vec![
+    ///     KeyStream::from_str("AABCD").unwrap(),
+    ///     KeyStream::from_str("EEEFG").unwrap(),
+    /// ];
+    /// let stats = KeyStreamStats::from_key_streams(&key_streams);
+    /// assert_eq!(stats.letters, 10);
+    /// assert_eq!(stats.letter_counts[4], 3);
+    /// assert_eq!((stats.pairs, stats.repeated_pairs), (8, 3));
+    /// assert_eq!(stats.repeat_rate, 3.0 / 8.0);
+    /// ```
+    pub fn from_key_streams(key_streams: &[KeyStream]) -> KeyStreamStats {
+        let mut letter_counts = [0u64; LETTERS];
+        let mut digram_counts = vec![0u64; LETTERS * LETTERS];
+        for ks in key_streams.iter() {
+            let values: Vec<usize> =
+                ks.0.iter()
+                    .map(|l| usize::from(u8::from(letter_into_value(l))) - 1)
+                    .collect();
+            for v in values.iter() {
+                letter_counts[*v] += 1;
+            }
+            for pair in values.windows(2) {
+                digram_counts[pair[0] * LETTERS + pair[1]] += 1;
+            }
+        }
+        let letters: u64 = letter_counts.iter().sum();
+        let pairs: u64 = digram_counts.iter().sum();
+        let repeated_pairs: u64 = (0..LETTERS).map(|i| digram_counts[i * LETTERS + i]).sum();
+
+        let expected_repeat_rate = 1.0 / LETTERS as f64;
+        let repeat_rate = ratio(repeated_pairs, pairs);
+        let repeat_sd = (expected_repeat_rate * (1.0 - expected_repeat_rate) / pairs as f64).sqrt();
+        let repeat_z_score = if pairs == 0 {
+            0.0
+        } else {
+            (repeat_rate - expected_repeat_rate) / repeat_sd
+        };
+
+        KeyStreamStats {
+            key_streams: key_streams.len(),
+            letters,
+            letter_counts,
+            letter_chi_square: chi_square(&letter_counts),
+            pairs,
+            repeated_pairs,
+            repeat_rate,
+            expected_repeat_rate,
+            repeat_z_score,
+            digram_chi_square: chi_square(&digram_counts),
+        }
+    }
+
+    /// Fraction of all letters which are the given letter index (0 for A through 25 for Z)
+    pub fn letter_frequency(&self, index: usize) -> f64 {
+        ratio(self.letter_counts[index], self.letters)
+    }
+}
+
+impl Display for KeyStreamStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "key streams: {}  letters: {}",
+            self.key_streams, self.letters
+        )?;
+        writeln!(f, "letter frequencies (ideal {:.4}):", 1.0 / LETTERS as f64)?;
+        for row in 0..LETTERS.div_ceil(6) {
+            let line: Vec<String> = (row * 6..(row * 6 + 6).min(LETTERS))
+                .map(|i| {
+                    format!(
+                        "{}: {:.4}",
+                        (b'A' + i as u8) as char,
+                        self.letter_frequency(i)
+                    )
+                })
+                .collect();
+            writeln!(f, "  {}", line.join("  "))?;
+        }
+        writeln!(
+            f,
+            "letter chi-square: {:.1} (25 degrees of freedom)",
+            self.letter_chi_square
+        )?;
+        writeln!(
+            f,
+            "repeated pairs: {} of {} = {:.5} (ideal {:.5}, z-score {:.1})",
+            self.repeated_pairs,
+            self.pairs,
+            self.repeat_rate,
+            self.expected_repeat_rate,
+            self.repeat_z_score
+        )?;
+        write!(
+            f,
+            "digram chi-square: {:.1} (675 degrees of freedom)",
+            self.digram_chi_square
+        )
+    }
+}
+
+/// Measure the KeyStreams (see DeckState::key_stream()) of exactly the given length from a number
+/// of random (Cards::shuffle_fy()) key decks
+///
+/// returns Err CypherError::KeyStreamTooShort should a deck fail to give its KeyStream (see
+/// DeckState::key_stream())
+///
+/// # Examples
+/// ```
+/// use solitaire_cypher::stats::sample_key_stream_stats;
+/// let stats = sample_key_stream_stats(10, 100).unwrap();
+/// assert_eq!(stats.letters, 10 * 100);
+/// println!("{}", stats);
+/// ```
+pub fn sample_key_stream_stats(
+    samples: usize,
+    length: usize,
+) -> Result<KeyStreamStats, CypherError> {
+    let key_streams = (0..samples)
+        .map(|_| {
+            // can panic if code broken - next line uses illegal joker count
+            let mut deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+            deck.shuffle_fy();
+            // can panic if code broken - a new deck always has every card
+            DeckState::from_cards(&deck).unwrap().key_stream(length)
+        })
+        .collect::<Result<Vec<KeyStream>, CypherError>>()?;
+    Ok(KeyStreamStats::from_key_streams(&key_streams))
+}
+
+fn ratio(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
+    }
+}
+
+// Pearson's chi-square statistic of the counts against all being equally likely
+fn chi_square(counts: &[u64]) -> f64 {
+    let total: u64 = counts.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+    let expected = total as f64 / counts.len() as f64;
+    counts
+        .iter()
+        .map(|c| (*c as f64 - expected).powi(2) / expected)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chi_square() {
+        assert_eq!(chi_square(&[10, 10, 10, 10]), 0.0);
+        // expected 10 each so (15^2 + 3 * 5^2) / 10
+        assert_eq!(chi_square(&[25, 5, 5, 5]), 30.0);
+        assert_eq!(chi_square(&[0, 0]), 0.0);
+    }
+
+    #[test]
+    fn test_solitaire_repeat_bias() {
+        // ~100k pairs is enough to show the excess of repeated letters
+        let stats = sample_key_stream_stats(200, 500).unwrap();
+        assert_eq!(stats.pairs, 200 * 499);
+        assert!(
+            stats.repeat_rate > stats.expected_repeat_rate,
+            "repeat rate {} not above 1/26",
+            stats.repeat_rate
+        );
+        assert!(stats.repeat_z_score > 3.0);
+    }
+}
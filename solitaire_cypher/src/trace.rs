@@ -0,0 +1,255 @@
+//! # Trace
+//!
+//! Step by step record of the Solitaire algorithm for those doing it by hand with a real deck.
+//! The deck is recorded (as card_play::Cards) after each sub-step of keying, further keying with
+//! a message indicator and KeyStream generation so the work can be checked as it goes.
+
+use crate::indicator::MessageIndicator;
+use crate::{
+    key_deck_from_passphrase_with, key_deck_with_letters, KeyStream, KeyingOptions,
+    KeystreamGenerator, OutputCard, Passphrase, UpperLetter,
+};
+use card_play::Cards;
+use std::fmt;
+use std::fmt::Display;
+
+/// The sub-steps of the Solitaire algorithm after which the deck is recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubStep {
+    /// Joker A moved down one card
+    JokerA,
+    /// Joker B moved down two cards
+    JokerB,
+    /// Cards above the first joker swapped with those below the second
+    TripleCut,
+    /// Count cut at the value of the bottom card
+    CountCut,
+    /// Keying only - count cut at the value of the passphrase (or message indicator) letter
+    PassphraseCut(UpperLetter),
+    /// Keying only - jokers placed using the last two passphrase letters
+    OptionalStep,
+    /// KeyStream only - output card found (deck is unchanged)
+    Output(OutputCard),
+}
+
+impl Display for SubStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubStep::JokerA => write!(f, "Joker A"),
+            SubStep::JokerB => write!(f, "Joker B"),
+            SubStep::TripleCut => write!(f, "Triple cut"),
+            SubStep::CountCut => write!(f, "Count cut"),
+            SubStep::PassphraseCut(letter) => write!(
+                f,
+                "Letter cut {} ({})",
+                u8::from(*letter) as char,
+                u8::from(*letter) - 64
+            ),
+            SubStep::OptionalStep => write!(f, "Optional step"),
+            SubStep::Output(output) => match output.letter {
+                Some(letter) => write!(
+                    f,
+                    "Output {} ({}) -> {}",
+                    output.card,
+                    output.value,
+                    u8::from(letter) as char
+                ),
+                None => write!(f, "Output {} ({}) skipped", output.card, output.value),
+            },
+        }
+    }
+}
+
+/// The deck following one sub-step
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    /// 1 based passphrase (or message indicator) letter number when keying or algorithm step
+    /// number (including those that output a joker) when generating a KeyStream
+    pub step: usize,
+    /// The sub-step just performed
+    pub sub_step: SubStep,
+    /// The deck after the sub-step
+    pub deck: Cards,
+}
+
+impl Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.sub_step {
+            SubStep::Output(_) => write!(f, "{:>4} {}", self.step, self.sub_step),
+            _ => write!(
+                f,
+                "{:>4} {:<16} {}",
+                self.step,
+                self.sub_step.to_string(),
+                self.deck
+            ),
+        }
+    }
+}
+
+/// A worksheet of the keying (empty if keyed some other way), message indicator (empty without
+/// one) and KeyStream generation (empty if only keying) traces, in the order they are worked
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Worksheet {
+    /// Trace of the creation of the key deck from a passphrase
+    pub keying: Vec<TraceEntry>,
+    /// Trace of the KeyStream of the key deck encrypting the message indicator (empty unless it
+    /// is sent encrypted)
+    pub indicator_key_stream: Vec<TraceEntry>,
+    /// Trace of the further keying of the key deck with the message indicator
+    pub indicator_keying: Vec<TraceEntry>,
+    /// Trace of the generation of the KeyStream from the key deck
+    pub key_stream: Vec<TraceEntry>,
+}
+
+impl Display for Worksheet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (heading, trace) in [
+            ("Keying", &self.keying),
+            ("Indicator key stream", &self.indicator_key_stream),
+            ("Indicator keying", &self.indicator_keying),
+            ("Key stream", &self.key_stream),
+        ] {
+            if !trace.is_empty() {
+                writeln!(f, "{}", heading)?;
+                for entry in trace.iter() {
+                    writeln!(f, "{}", entry)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Create a key deck from a Passphrase (see key_deck_from_passphrase_with_options()) recording the
+/// deck after every sub-step
+///
+/// # Examples
+/// ```
+/// use std::str::FromStr;
+/// use solitaire_cypher::{key_deck_from_passphrase, KeyingOptions, Passphrase};
+/// use solitaire_cypher::trace::{trace_key_deck_from_passphrase, SubStep};
+/// let passphrase = Passphrase::from_str("foo").unwrap();
+/// let (deck, trace) = trace_key_deck_from_passphrase(&passphrase, &KeyingOptions::default());
+/// assert_eq!(deck, key_deck_from_passphrase(&passphrase));
+/// assert_eq!(trace.len(), 3 * 5);
+/// assert_eq!(trace[0].sub_step, SubStep::JokerA);
+/// assert_eq!(trace[14].deck, deck);
+/// ```
+pub fn trace_key_deck_from_passphrase(
+    passphrase: &Passphrase,
+    options: &KeyingOptions,
+) -> (Cards, Vec<TraceEntry>) {
+    let mut trace = Vec::new();
+    let deck = key_deck_from_passphrase_with(passphrase, options, &mut |step, sub_step, deck| {
+        trace.push(TraceEntry {
+            step,
+            sub_step,
+            deck: deck.clone(),
+        })
+    });
+    (deck, trace)
+}
+
+/// Further key a key deck with a MessageIndicator (see MessageIndicator::key_deck()) recording
+/// the deck after every sub-step
+///
+/// # Examples
+/// ```
+/// use std::str::FromStr;
+/// use solitaire_cypher::{key_deck_from_passphrase, Passphrase};
+/// use solitaire_cypher::indicator::MessageIndicator;
+/// use solitaire_cypher::trace::{trace_indicator_key_deck, SubStep};
+/// let key_deck = key_deck_from_passphrase(&Passphrase::from_str("foo").unwrap());
+/// let indicator = MessageIndicator::from_str("AAAAB").unwrap();
+/// let (deck, trace) = trace_indicator_key_deck(&indicator, key_deck.clone());
+/// assert_eq!(deck, indicator.key_deck(key_deck));
+/// assert_eq!(trace.len(), 5 * 5);
+/// assert_eq!(trace[24].step, 5);
+/// assert_eq!(trace[24].sub_step.to_string(), "Letter cut B (2)");
+/// ```
+pub fn trace_indicator_key_deck(
+    indicator: &MessageIndicator,
+    key_deck: Cards,
+) -> (Cards, Vec<TraceEntry>) {
+    let mut trace = Vec::new();
+    let deck = key_deck_with_letters(key_deck, &indicator.0, &mut |step, sub_step, deck| {
+        trace.push(TraceEntry {
+            step,
+            sub_step,
+            deck: deck.clone(),
+        })
+    });
+    (deck, trace)
+}
+
+/// Create a KeyStream of exactly the specified length (no rounding up to a multiple of 5, see
+/// KeystreamGenerator::key_stream()) recording the deck after every sub-step
+///
+/// # Examples
+/// ```
+/// use card_play::{Cards, JokersPerDeck};
+/// use solitaire_cypher::trace::{trace_key_stream, SubStep};
+/// let deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+/// let (ks, trace) = trace_key_stream(deck, 5);
+/// assert_eq!(ks.to_string(), "DWJXH");
+/// // the fourth step outputs a joker so six steps are needed for five letters
+/// assert_eq!(trace.len(), 6 * 5);
+/// assert!(matches!(trace[19].sub_step, SubStep::Output(o) if o.letter.is_none()));
+/// ```
+pub fn trace_key_stream(key_deck: Cards, key_length: usize) -> (KeyStream, Vec<TraceEntry>) {
+    let mut trace = Vec::new();
+    let mut generator = KeystreamGenerator::new(key_deck);
+    let mut key_stream = KeyStream::new();
+    let mut step = 0;
+    while key_stream.len() < key_length {
+        step += 1;
+        let output = generator.next_output_with(&mut |sub_step, deck| {
+            trace.push(TraceEntry {
+                step,
+                sub_step,
+                deck: deck.clone(),
+            })
+        });
+        if let Some(letter) = output.letter {
+            key_stream.0.push(letter);
+        }
+    }
+    (key_stream, trace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_key_stream, key_deck_from_passphrase};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_traces_match_untraced() {
+        let pp = Passphrase::from_str("cryptonomicon").unwrap();
+        let options = KeyingOptions {
+            optional_step: true,
+        };
+        let (deck, keying) = trace_key_deck_from_passphrase(&pp, &options);
+        assert_eq!(keying.len(), pp.len() * 5 + 1);
+        assert_eq!(keying.last().unwrap().sub_step, SubStep::OptionalStep);
+
+        let (ks, key_stream) = trace_key_stream(deck.clone(), 23);
+        assert_eq!(ks.len(), 23);
+        assert_eq!(ks.0, get_key_stream(deck, 23).0[..23]);
+        let letters: Vec<UpperLetter> = key_stream
+            .iter()
+            .filter_map(|entry| match entry.sub_step {
+                SubStep::Output(output) => output.letter,
+                _ => None,
+            })
+            .collect();
+        assert_eq!(letters, ks.0);
+
+        // each traced step leads on from the deck of the previous one
+        let pp = Passphrase::from_str("bcd").unwrap();
+        let (_, keying) = trace_key_deck_from_passphrase(&pp, &KeyingOptions::default());
+        assert_eq!(keying.last().unwrap().deck, key_deck_from_passphrase(&pp));
+        assert_eq!(keying[4].sub_step.to_string(), "Letter cut B (2)");
+    }
+}
@@ -0,0 +1,151 @@
+//! # Transliterate
+//!
+//! Solitaire only works with the letters A-Z so accented and other non-ASCII letters are folded
+//! into their closest ASCII spelling before use, e.g. "Café Müller" becomes "Cafe Muller" rather
+//! than losing its accented letters.  Diacritics are removed by Unicode compatibility
+//! decomposition (NFKD), letters without a decomposition (e.g. ß, Æ, Œ, Ø, Ł, Þ) are expanded
+//! from a table, and typographic quotes and dashes become their ASCII equivalents.  Anything else
+//! (e.g. Greek or Cyrillic letters, currency symbols) can't be mapped and is reported.
+
+use std::fmt;
+use std::fmt::Display;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Letters and symbols without a (useful) decomposition and their ASCII spellings
+const SPECIAL: [(char, &str); 32] = [
+    ('ß', "ss"),
+    ('ẞ', "SS"),
+    ('æ', "ae"),
+    ('Æ', "AE"),
+    ('œ', "oe"),
+    ('Œ', "OE"),
+    ('ø', "o"),
+    ('Ø', "O"),
+    ('ł', "l"),
+    ('Ł', "L"),
+    ('þ', "th"),
+    ('Þ', "TH"),
+    ('ð', "d"),
+    ('Ð', "D"),
+    ('đ', "d"),
+    ('Đ', "D"),
+    ('ħ', "h"),
+    ('Ħ', "H"),
+    ('ı', "i"),
+    ('‘', "'"),
+    ('’', "'"),
+    ('‚', "'"),
+    ('′', "'"),
+    ('“', "\""),
+    ('”', "\""),
+    ('„', "\""),
+    ('″', "\""),
+    ('«', "\""),
+    ('»', "\""),
+    ('–', "-"),
+    ('—', "-"),
+    ('−', "-"),
+];
+
+/// A character which couldn't be transliterated into ASCII
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unmapped {
+    /// Zero based character (not byte) position within the text
+    pub position: usize,
+    /// The character
+    pub character: char,
+}
+
+impl Display for Unmapped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "'{}' (U+{:04X}) at position {}",
+            self.character, self.character as u32, self.position
+        )
+    }
+}
+
+/// Text transliterated into ASCII along with the characters which couldn't be
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transliteration {
+    /// The ASCII text, without the unmapped characters
+    pub text: String,
+    /// The characters left out of the text
+    pub unmapped: Vec<Unmapped>,
+}
+
+/// Transliterate text into ASCII (see transliterate_char())
+///
+/// # Examples
+/// ```
+/// use solitaire_cypher::transliterate::{transliterate, Unmapped};
+/// let result = transliterate("Café Müller, Straße 5 – €10");
+/// assert_eq!(result.text, "Cafe Muller, Strasse 5 - 10");
+/// assert_eq!(result.unmapped, vec![Unmapped { position: 24, character: '€' }]);
+/// ```
+pub fn transliterate(text: &str) -> Transliteration {
+    let mut result = Transliteration {
+        text: String::with_capacity(text.len()),
+        ..Default::default()
+    };
+    for (position, character) in text.chars().enumerate() {
+        if !transliterate_char(character, |c| result.text.push(c)) {
+            result.unmapped.push(Unmapped {
+                position,
+                character,
+            });
+        }
+    }
+    result
+}
+
+/// Pass the ASCII spelling of a character, one or more characters, to push.  ASCII characters are
+/// passed unchanged.
+///
+/// returns false, having pushed nothing, if the character can't be transliterated
+///
+/// # Examples
+/// ```
+/// use solitaire_cypher::transliterate::transliterate_char;
+/// let mut letters = String::new();
+/// for c in ['Å', 'ß', 'ﬁ', 'Ж'] {
+///     transliterate_char(c, |c| letters.push(c));
+/// }
+/// assert_eq!(letters, "Assfi");
+/// assert!(!transliterate_char('Ж', |_| {}));
+/// ```
+pub fn transliterate_char(c: char, mut push: impl FnMut(char)) -> bool {
+    if c.is_ascii() {
+        push(c);
+        return true;
+    }
+    if let Some((_, spelling)) = SPECIAL.iter().find(|(special, _)| *special == c) {
+        spelling.chars().for_each(push);
+        return true;
+    }
+    let decomposed = || std::iter::once(c).nfkd().filter(|d| !is_combining_mark(*d));
+    if decomposed().next().is_none() || !decomposed().all(|d| d.is_ascii()) {
+        return false;
+    }
+    decomposed().for_each(push);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transliterate() {
+        let result = transliterate("ÀÉÎÕÜÇÑ àéîõüçñ Ærøskøbing Œuvre Łódź Þór Ðorđe ﬂ ½ “Ἀθῆναι”");
+        assert_eq!(
+            result.text,
+            "AEIOUCN aeioucn AEroskobing OEuvre Lodz THor Dorde fl  \"\""
+        );
+        let unmapped: String = result.unmapped.iter().map(|u| u.character).collect();
+        assert_eq!(unmapped, "½Ἀθῆναι");
+        assert_eq!(result.unmapped[0].position, 50);
+    }
+}
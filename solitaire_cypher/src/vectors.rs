@@ -0,0 +1,363 @@
+//! # Vectors
+//!
+//! Parser and runner for test vectors in the format of Schneier's sol-test.txt (see
+//! TEST_VECTORS) so any Solitaire implementation, including someone learning with a real deck,
+//! can be checked against the reference set.  Each vector is a group of lines:
+//!
+//! ```text
+//! Plaintext:  AAAAAAAAAAAAAAA
+//! Key:  'bcd'
+//! Output:  5 38 20 27 50 1 38 26 49 33 39 42 49 2 35
+//! Ciphertext:  FMUBY BMAXH NQXCJ
+//! ```
+//!
+//! The Output line (output card values, including skipped jokers) is optional and a Key of
+//! `<null key>` means the unkeyed new deck.
+
+use crate::{
+    get_output_cards, key_deck_from_passphrase_with_options, try_decrypt, try_encrypt, CardValue,
+    CypherText, KeyingOptions, KeystreamGenerator, Passphrase, PlainText,
+};
+use card_play::{Cards, JokersPerDeck};
+use std::fmt;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// A test vector's contents could not be understood
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VectorParseError {
+    /// 1 based line number of the offending line
+    pub line: usize,
+    /// What was wrong with it
+    pub reason: String,
+}
+
+impl Display for VectorParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "test vector line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for VectorParseError {}
+
+/// One test vector
+#[derive(Debug)]
+pub struct TestVector {
+    /// 1 based line number of the vector's Plaintext line
+    pub line: usize,
+    /// The plaintext (padded with X to a multiple of five letters)
+    pub plaintext: PlainText,
+    /// The passphrase keying the deck, None for the unkeyed new deck
+    pub key: Option<Passphrase>,
+    /// The output card values expected, if listed
+    pub output: Option<Vec<CardValue>>,
+    /// The ciphertext expected
+    pub ciphertext: CypherText,
+}
+
+impl TestVector {
+    /// The key as written in the vector format, e.g. 'foo' or <null key>
+    pub fn key_name(&self) -> String {
+        match &self.key {
+            Some(passphrase) => format!("'{}'", passphrase.to_string().to_lowercase()),
+            None => "<null key>".to_string(),
+        }
+    }
+
+    /// Compute the vector's Output (if listed), Ciphertext and Plaintext (by decrypting the
+    /// expected Ciphertext) and compare each with that expected
+    ///
+    /// # Examples
+    /// ```
+    /// use solitaire_cypher::KeyingOptions;
+    /// use solitaire_cypher::vectors::parse_test_vectors;
+    /// let vectors = parse_test_vectors(
+    ///     "Plaintext:  AAAAAAAAAAAAAAA\n\
+    ///      Key:  'bcd'\n\
+    ///      Output:  5 38 20 27 50 1 38 26 49 33 39 42 49 2 35\n\
+    ///      Ciphertext:  FMUBY BMAXH NQXCI\n",
+    /// )
+    /// .unwrap();
+    /// let result = vectors[0].run(&KeyingOptions::default());
+    /// assert!(!result.passed());
+    /// assert_eq!(result.mismatches.len(), 2);
+    /// assert_eq!(result.mismatches[0].computed, "FMUBY BMAXH NQXCJ");
+    /// assert_eq!(result.mismatches[0].first_difference(), 16);
+    /// ```
+    pub fn run(&self, options: &KeyingOptions) -> VectorResult {
+        let key_deck = match &self.key {
+            Some(passphrase) => key_deck_from_passphrase_with_options(passphrase, options),
+            // can panic if code broken - next line uses illegal joker count
+            None => Cards::new(1, JokersPerDeck::new(2).unwrap()),
+        };
+        let mut mismatches = Vec::new();
+        let mut check = |field: Field, expected: String, computed: String| {
+            if expected != computed {
+                mismatches.push(Mismatch {
+                    field,
+                    expected,
+                    computed,
+                });
+            }
+        };
+
+        if let Some(output) = &self.output {
+            let show = |values: Vec<CardValue>| -> String {
+                values
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            };
+            let computed = get_output_cards(key_deck.clone(), self.plaintext.len())
+                .iter()
+                .map(|o| o.value)
+                .collect();
+            check(Field::Output, show(output.clone()), show(computed));
+        }
+
+        let key_stream = KeystreamGenerator::new(key_deck)
+            .key_stream(self.plaintext.len().max(self.ciphertext.len()));
+        // can panic if code broken - the key stream is as long as both texts
+        let computed_ct = try_encrypt(&self.plaintext, &key_stream).unwrap();
+        check(
+            Field::Ciphertext,
+            self.ciphertext.to_string(),
+            computed_ct.to_string(),
+        );
+        // can panic if code broken - the key stream is as long as both texts
+        let computed_pt = try_decrypt(&self.ciphertext, &key_stream).unwrap();
+        check(
+            Field::Plaintext,
+            self.plaintext.to_string(),
+            computed_pt.to_string(),
+        );
+
+        VectorResult {
+            line: self.line,
+            key: self.key_name(),
+            mismatches,
+        }
+    }
+}
+
+/// The test vector lines which can be checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// Output card values
+    Output,
+    /// Ciphertext from encrypting the Plaintext
+    Ciphertext,
+    /// Plaintext from decrypting the Ciphertext
+    Plaintext,
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Field::Output => "Output",
+            Field::Ciphertext => "Ciphertext",
+            Field::Plaintext => "Plaintext",
+        };
+        f.pad(name)
+    }
+}
+
+/// A computed line which differs from that expected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// The line compared
+    pub field: Field,
+    /// The line's contents as given in the vector
+    pub expected: String,
+    /// The line's contents as computed
+    pub computed: String,
+}
+
+impl Mismatch {
+    /// Zero based character position of the first difference between expected and computed
+    pub fn first_difference(&self) -> usize {
+        self.expected
+            .chars()
+            .zip(self.computed.chars())
+            .position(|(e, c)| e != c)
+            .unwrap_or_else(|| self.expected.len().min(self.computed.len()))
+    }
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>10} expected {}", self.field, self.expected)?;
+        writeln!(f, "{:>10} computed {}", "", self.computed)?;
+        write!(f, "{:>width$}^", "", width = 20 + self.first_difference())
+    }
+}
+
+/// The outcome of running one test vector
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VectorResult {
+    /// 1 based line number of the vector's Plaintext line
+    pub line: usize,
+    /// The key as written in the vector format
+    pub key: String,
+    /// Every computed line differing from that expected, empty if the vector passed
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl VectorResult {
+    /// True if everything computed matched the vector
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl Display for VectorResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = if self.passed() { "ok" } else { "FAILED" };
+        write!(f, "{:<7} line {:<4} key {}", status, self.line, self.key)?;
+        for mismatch in self.mismatches.iter() {
+            write!(f, "\n{}", mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse test vectors in the sol-test.txt format.  Blank lines are ignored and line endings may
+/// be \n or \r\n.
+///
+/// # Examples
+/// ```
+/// use solitaire_cypher::TEST_VECTORS;
+/// use solitaire_cypher::vectors::parse_test_vectors;
+/// let vectors = parse_test_vectors(TEST_VECTORS).unwrap();
+/// assert_eq!(vectors.len(), 12);
+/// assert_eq!(vectors[3].key_name(), "'foo'");
+/// assert_eq!(vectors[3].output.as_ref().unwrap().len(), 17);
+/// assert!(parse_test_vectors("Key: 'foo'").is_err());
+/// ```
+pub fn parse_test_vectors(s: &str) -> Result<Vec<TestVector>, VectorParseError> {
+    let mut vectors = Vec::new();
+    let mut plaintext: Option<(usize, PlainText)> = None;
+    let mut key: Option<Option<Passphrase>> = None;
+    let mut output: Option<Vec<CardValue>> = None;
+
+    for (i, line) in s.lines().enumerate() {
+        let line_number = i + 1;
+        let error = |reason: &str| VectorParseError {
+            line: line_number,
+            reason: reason.to_string(),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some((label, value)) = line.split_once(':') else {
+            return Err(error("expected a label such as Plaintext:"));
+        };
+        let value = value.trim();
+        match label.trim() {
+            "Plaintext" => {
+                if plaintext.is_some() {
+                    return Err(error("previous vector has no Ciphertext"));
+                }
+                // can't fail - PlainText::from_str() is Infallible
+                plaintext = Some((line_number, PlainText::from_str(value).unwrap()));
+            }
+            "Key" => {
+                if plaintext.is_none() {
+                    return Err(error("Key before Plaintext"));
+                }
+                key = Some(if value == "<null key>" {
+                    None
+                } else {
+                    Some(Passphrase::from_str(value).map_err(|e| error(&e.to_string()))?)
+                });
+            }
+            "Output" => {
+                let values = value
+                    .split_whitespace()
+                    .map(|v| v.parse::<u8>().ok().and_then(CardValue::new))
+                    .collect::<Option<Vec<CardValue>>>();
+                output = Some(values.ok_or_else(|| error("Output values must be 1 to 53"))?);
+            }
+            "Ciphertext" => {
+                let (Some((line, plaintext)), Some(key)) = (plaintext.take(), key.take()) else {
+                    return Err(error("Ciphertext before Plaintext and Key"));
+                };
+                let ciphertext = CypherText::from_str(value).map_err(|e| error(&e.to_string()))?;
+                vectors.push(TestVector {
+                    line,
+                    plaintext,
+                    key,
+                    output: output.take(),
+                    ciphertext,
+                });
+            }
+            _ => return Err(error("unknown label")),
+        }
+    }
+    if let Some((line, _)) = plaintext {
+        return Err(VectorParseError {
+            line,
+            reason: "vector has no Ciphertext".to_string(),
+        });
+    }
+    Ok(vectors)
+}
+
+/// Parse (see parse_test_vectors()) and run (see TestVector::run()) test vectors
+///
+/// # Examples
+/// ```
+/// use solitaire_cypher::{KeyingOptions, TEST_VECTORS};
+/// use solitaire_cypher::vectors::run_test_vectors;
+/// let results = run_test_vectors(TEST_VECTORS, &KeyingOptions::default()).unwrap();
+/// assert!(results.iter().all(|r| r.passed()));
+/// println!("{}", results[0]);
+/// ```
+pub fn run_test_vectors(
+    s: &str,
+    options: &KeyingOptions,
+) -> Result<Vec<VectorResult>, VectorParseError> {
+    Ok(parse_test_vectors(s)?
+        .iter()
+        .map(|vector| vector.run(options))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mismatch_display() {
+        let results = run_test_vectors(
+            "Plaintext: SOLITAIRE\r\n\
+             Key:  'cryptonomicon'\r\n\
+             Ciphertext:  KIRAK SFJAM\r\n",
+            &KeyingOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            results[0].to_string(),
+            "FAILED  line 1    key 'cryptonomicon'\n\
+             Ciphertext expected KIRAK SFJAM\n\
+             \x20          computed KIRAK SFJAN\n\
+             \x20                             ^\n\
+             \x20Plaintext expected SOLITAIREX\n\
+             \x20          computed SOLITAIREW\n\
+             \x20                            ^"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let line = |s: &str| parse_test_vectors(s).unwrap_err().line;
+        assert_eq!(line("Plaintext: AAAAA\n\nPlaintext: AAAAA"), 3);
+        assert_eq!(line("Plaintext: AAAAA\nKey: '4'"), 2);
+        assert_eq!(line("Plaintext: AAAAA\nKey: 'a'\nOutput: 1 54"), 3);
+        assert_eq!(line("Plaintext: AAAAA\nKey: 'a'\nCyphertext: AAAAA"), 3);
+        assert_eq!(line("\nPlaintext: AAAAA\nKey: 'a'"), 2);
+        assert_eq!(line("AAAAA"), 1);
+        assert!(parse_test_vectors("").unwrap().is_empty());
+    }
+}
@@ -3,57 +3,484 @@
 //!
 //! An implementation of the playing card based cypher created by
 //! Bruce Schneier and featured in Neal Stephenson’s "Cryptonomicon".
-//! Encrypts or Decrypts stdin to stdout based on the command line provided passphrase.
-//! Returns error if the passphrase includes any non-letter characters.
+//! Encrypts or Decrypts stdin to stdout based on a passphrase or a pre-arranged key deck, which
+//! may be generated with the keygen command.  To keep the passphrase out of shell history and
+//! process listings it may be read from a file or an environment variable, and is prompted for
+//! (without echo) if no key option is given.
+//! Accented and other non-ASCII letters are transliterated (e.g. "Café Müller" to CAFEMULLER) with a
+//! warning for any characters that can't be.
+//! Decryption fails on anything in the ciphertext other than letters and whitespace unless --lenient.
+//! A message indicator (--indicator) gives every message its own keystream so a key can be reused.
+//! Messages encrypted with the same keystream (in depth) can be found with analyze depth, which
+//! compares the messages aligned from their first letters (after the indicator with --indicator).
+//! Returns error if the passphrase includes any non-letter characters.  The keystream and key deck
+//! can also be printed (e.g. to check work done by hand) and the implementation checked against
+//! the published test vectors.
 //! Crate solitaire_cypher exists to provide these, and more, functions in a lib.
 //! See: <https://www.schneier.com/academic/solitaire/> and, of course, read Cryptonomicon!
 //!
 //! #Examples
 //! ```
-//!$ ./solitaire_cypher --help
-//! Usage: solitaire_cypher --passphrase <PASSPHRASE> <--encrypt|--decrypt>
+//! $ solitaire_cypher_cli --help
+//! Usage: solitaire_cypher_cli <COMMAND>
+//!
+//! Commands:
+//!   encrypt    Encrypt stdin with keystream generated from the key
+//!   decrypt    Decrypt stdin with keystream generated from the key
+//!   keystream  Print keystream letters generated from the key
+//!   keydeck    Print the key deck produced from the key
+//!   selftest   Check this implementation against the published (or other) test vectors
+//!   keygen     Generate a random key deck for use with --deck or --deck-file
+//!   stats      Print keystream bias statistics measured from random key decks
+//!   analyze    Analyze ciphertexts for weaknesses
+//!   help       Print this message or the help of the given subcommand(s)
+//!
+//! Options:
+//!   -h, --help     Print help
+//!   -V, --version  Print version
+//! $ solitaire_cypher_cli encrypt --help
+//! Encrypt stdin with keystream generated from the key
+//!
+//! Usage: solitaire_cypher_cli encrypt [OPTIONS]
 //!
 //! Options:
-//! -e, --encrypt                  Encrypt stdin with keystream generated from passphrase
-//! -d, --decrypt                  Decrypt stdin with keystream generated from passphrase
-//! -p, --passphrase <PASSPHRASE>  passphrase (letters only) for key generation
-//! -h, --help                     Print help
-//! -V, --version                  Print version
-//! $ echo "SOLITAIRE" | ./solitaire_cypher --passphrase cryptonomicon --encrypt
+//!   -p, --passphrase <PASSPHRASE>      passphrase for (letters only) key generation
+//!       --passphrase-file <FILE>       read the passphrase from the first line of a file
+//!       --passphrase-env <VAR>         read the passphrase from an environment variable
+//!       --deck <DECK>                  key deck (all 54 cards, e.g. "AH 2H .. FA FB") to use instead of a passphrase
+//!       --deck-file <FILE>             read the key deck from a file (cards separated by spaces or line breaks)
+//!       --optional-step                use the last two passphrase letters to place the jokers (Schneier's "Optional step")
+//!       --padding <PADDING>            padding scheme - marked ends the padding with a letter A-E giving its length so it can be stripped exactly, as the escape encoding needs [default: letter] [possible values: letter, marked, none]
+//!       --pad-letter <LETTER>          letter used for padding, not the escape letter with the escape encoding [default: X]
+//!       --encoding <ENCODING>          letters drops everything else, escape encodes digits, spaces and punctuation as the escape letter followed by a code letter [default: letters] [possible values: letters, escape]
+//!       --escape-letter <LETTER>       escape letter for the escape encoding [default: Z]
+//!       --group-size <N>               letters in each group, 0 for no groups [default: 5]
+//!       --groups-per-line <N>          groups on each line, 0 for all on one line [default: 0]
+//!       --numbering <NUMBERING>        number each line with its line number or the number of its first group [default: none] [possible values: none, lines, groups]
+//!       --radiogram [<NUMBER>]         radiogram layout with an "NR <NUMBER> GR <GROUPS>" header line and a final "AR" line
+//!       --indicator                    start the ciphertext with a message indicator which further keys the deck (a keystream per message)
+//!       --indicator-length <N>         letters in the message indicator [default: 5]
+//!       --indicator-mode <MODE>        send the message indicator as is or encrypted with the unmodified key deck [default: clear] [possible values: clear, encrypted]
+//!       --indicator-letters <LETTERS>  message indicator letters (e.g. a message counter) instead of random, setting the indicator length - never reuse one with a key
+//!       --no-pad                       don't pad the last group to 5 letters (same as --padding none)
+//!       --trace <TRACE>                print a worksheet of the deck after every algorithm sub-step to stderr [possible values: text, json]
+//!   -h, --help                         Print help
+//! $ echo "SOLITAIRE" | solitaire_cypher_cli encrypt --passphrase cryptonomicon
 //! KIRAK SFJAN
-//! $ echo "KIRAK SFJAN" | ./solitaire_cypher --passphrase cryptonomicon --decrypt
+//! $ echo "KIRAK SFJAN" | solitaire_cypher_cli decrypt --passphrase cryptonomicon
+//! SOLITAIREX
+//! $ echo "KIR4K SFJAN" | solitaire_cypher_cli decrypt --passphrase cryptonomicon
+//! string contains invalid symbols: '4' at position 3
+//! Error: IllegalInputFormatError - only letters allowed
+//! $ echo "KIR4K SFJAN" | solitaire_cypher_cli decrypt --passphrase cryptonomicon --lenient
+//! warning: ciphertext length 9 is not a multiple of 5 - letters may be missing
+//! SOLSBNMIR
+//! $ echo "KIRAK SFJAN" | solitaire_cypher_cli decrypt
+//! Passphrase:
+//! SOLITAIREX
+//! $ echo "SOLITAIRE" | solitaire_cypher_cli encrypt --passphrase cryptonomicon --padding marked
+//! KIRAK SFJAQ
+//! $ echo "KIRAK SFJAQ" | solitaire_cypher_cli decrypt --passphrase cryptonomicon --padding marked --strip-pad
+//! SOLITAIRE
+//! $ echo "Meet at 0930" | solitaire_cypher_cli encrypt --passphrase cryptonomicon --encoding escape --padding marked
+//! EYKLQ CXLVA QMWXG LOBCQ
+//! $ echo "EYKLQ CXLVA QMWXG LOBCQ" | solitaire_cypher_cli decrypt --passphrase cryptonomicon --encoding escape --padding marked --strip-pad
+//! MEET AT 0930
+//! $ solitaire_cypher_cli keystream 10 --passphrase cryptonomicon
+//! RTFRQ RWRVP
+//! $ echo "SOLITAIRE" | solitaire_cypher_cli encrypt --passphrase cryptonomicon --indicator
+//! RFCZX NGJYJ PBUBK
+//! $ echo "RFCZX NGJYJ PBUBK" | solitaire_cypher_cli decrypt --passphrase cryptonomicon --indicator
 //! SOLITAIREX
+//! $ solitaire_cypher_cli keystream 60 --passphrase cryptonomicon --groups-per-line 5 --numbering groups --radiogram 7
+//! NR 7 GR 12
+//!  1  RTFRQ RWRVP QLWNG HOAEO WZQXP
+//!  6  IKFFJ DXQVK IKEAA AGMDZ EVBBB
+//! 11  WOYOF IEVIW
+//! AR
+//! $ solitaire_cypher_cli keydeck --passphrase foo --numeric
+//! 22 23 24 25 26 27 28 29 30 31 32 33 34 35 36 37 38 39 40 41 42 43 44 45 46 47 48 49 50 51 3 4 5 6 7 1 10 11 12 52 53 8 9 54 13 14 15 16 17 18 19 20 21 2
+//! $ solitaire_cypher_cli keygen --output key_deck.txt
+//! $ echo "SOLITAIRE" | solitaire_cypher_cli encrypt --deck-file key_deck.txt
+//! $ solitaire_cypher_cli selftest
+//! ok      line 1    key <null key>
+//! ...
+//! ok      line 55   key 'cryptonomicon'
+//! 12 passed, 0 failed
+//! $ solitaire_cypher_cli selftest --file my-vectors.txt
+//! FAILED  line 1    key 'foo'
+//! Ciphertext expected ITHZU JIWGR FARMX
+//!            computed ITHZU JIWGR FARMW
+//!                                     ^
+//!  Plaintext expected AAAAAAAAAAAAAAA
+//!            computed AAAAAAAAAAAAAAB
+//!                                   ^
+//! 0 passed, 1 failed
+//! Error: SelftestFailedError - 1 test vector(s) failed
+//! $ solitaire_cypher_cli analyze depth messages
+//! DEPTH  msg1.txt  msg3.txt  overlap 380   coincidences 32   kappa 0.0842  difference IC 1.071  z-score 3.8
+//! 3 messages, 3 pairs, 1 likely in depth
+//! Error: DepthFoundError - 1 pair(s) of messages likely in depth
 //! $
 //! ```
 
-use anyhow::Result;
-use clap::{Args, Parser};
+use anyhow::{anyhow, Context, Result};
+use card_play::Cards;
+use card_play::JokersPerDeck;
+use clap::builder::RangedU64ValueParser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use serde_json::json;
+use solitaire_cypher::encoding::Encoding;
+use solitaire_cypher::indicator::{IndicatorMode, MessageIndicator, DEFAULT_INDICATOR_LENGTH};
+use solitaire_cypher::layout::{GroupFormat, Numbering};
+use solitaire_cypher::trace::*;
+use solitaire_cypher::transliterate::transliterate;
 use solitaire_cypher::*;
 use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
 use std::str::FromStr;
-use std::{fmt, io};
+use std::{env, fmt, fs, io};
+use zeroize::Zeroizing;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
-    /// Mutually exclusive command flags
-    #[command(flatten)]
-    cmd: Cmd,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Encrypt stdin with keystream generated from the key
+    Encrypt {
+        #[command(flatten)]
+        key: KeyArgs,
+        #[command(flatten)]
+        pad: PadArgs,
+        #[command(flatten)]
+        encoding: EncodingArgs,
+        #[command(flatten)]
+        layout: LayoutArgs,
+        #[command(flatten)]
+        indicator: IndicatorArgs,
+        /// message indicator letters (e.g. a message counter) instead of random, setting the indicator length - never reuse one with a key
+        #[arg(long, value_name = "LETTERS", requires = "indicator", conflicts_with = "indicator_length", value_parser = parse_indicator)]
+        indicator_letters: Option<MessageIndicator>,
+        /// don't pad the last group to 5 letters (same as --padding none)
+        #[arg(long, conflicts_with_all = ["padding", "pad_letter"])]
+        no_pad: bool,
+        /// print a worksheet of the deck after every algorithm sub-step to stderr
+        #[arg(long, value_enum)]
+        trace: Option<TraceFormat>,
+    },
+    /// Decrypt stdin with keystream generated from the key
+    Decrypt {
+        #[command(flatten)]
+        key: KeyArgs,
+        #[command(flatten)]
+        pad: PadArgs,
+        #[command(flatten)]
+        encoding: EncodingArgs,
+        #[command(flatten)]
+        layout: LayoutArgs,
+        #[command(flatten)]
+        indicator: IndicatorArgs,
+        /// remove the padding (exactly if marked, otherwise up to 4 trailing pad letters)
+        #[arg(long)]
+        strip_pad: bool,
+        /// skip anything in the ciphertext other than letters instead of failing
+        #[arg(long)]
+        lenient: bool,
+        /// print a worksheet of the deck after every algorithm sub-step to stderr
+        #[arg(long, value_enum)]
+        trace: Option<TraceFormat>,
+    },
+    /// Print keystream letters generated from the key
+    Keystream {
+        /// number of keystream letters (exactly, no rounding up to a multiple of 5)
+        length: usize,
+        #[command(flatten)]
+        key: KeyArgs,
+        #[command(flatten)]
+        layout: LayoutArgs,
+    },
+    /// Print the key deck produced from the key
+    Keydeck {
+        #[command(flatten)]
+        key: KeyArgs,
+        /// print the deck as numbers 1-54 (new deck order with FA 53 and FB 54) instead of card names
+        #[arg(long)]
+        numeric: bool,
+        /// print a worksheet of the deck after every keying sub-step to stderr
+        #[arg(long, value_enum)]
+        trace: Option<TraceFormat>,
+    },
+    /// Check this implementation against the published (or other) test vectors
+    Selftest {
+        /// read test vectors in the published sol-test.txt format from a file
+        #[arg(long, value_name = "FILE")]
+        file: Option<PathBuf>,
+        /// key the test vectors with Schneier's "Optional step"
+        #[arg(long)]
+        optional_step: bool,
+    },
+    /// Generate a random key deck for use with --deck or --deck-file
+    Keygen {
+        /// print the deck as numbers 1-54 (new deck order with FA 53 and FB 54) instead of card names
+        #[arg(long)]
+        numeric: bool,
+        /// seed the shuffle for a reproducible deck, e.g. for test fixtures (never for real keys)
+        #[arg(long)]
+        seed: Option<u64>,
+        /// write the deck to a file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Print keystream bias statistics measured from random key decks
+    Stats {
+        /// number of random key decks sampled
+        #[arg(long, default_value_t = 1000)]
+        samples: usize,
+        /// length of each key stream sampled
+        #[arg(long, default_value_t = 1000)]
+        length: usize,
+    },
+    /// Analyze ciphertexts for weaknesses
+    Analyze {
+        #[command(subcommand)]
+        command: AnalyzeCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum AnalyzeCommand {
+    /// Find pairs of messages likely encrypted with the same keystream (in depth)
+    Depth {
+        /// directory of ciphertext files, one message per file
+        dir: PathBuf,
+        /// z-score of the index of coincidence of the aligned letter differences at or above which a
+        /// pair is flagged - lower finds shorter messages in depth but flags more unrelated pairs
+        /// (about 1 in 60 at 2.5, 1 in 150 at 3.0)
+        #[arg(long, value_name = "Z", default_value_t = depth::DEFAULT_THRESHOLD)]
+        threshold: f64,
+        /// print every pair, not just those flagged
+        #[arg(long)]
+        all: bool,
+        /// skip the message indicator at the start of each ciphertext, comparing just the messages
+        #[arg(long)]
+        indicator: bool,
+        /// letters in the message indicator
+        #[arg(long, value_name = "N", default_value_t = DEFAULT_INDICATOR_LENGTH, requires = "indicator",
+            value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+        indicator_length: usize,
+        #[command(flatten)]
+        layout: LayoutArgs,
+    },
+}
 
+/// Where the key deck comes from - prompted for as a passphrase if none given
+#[derive(Args)]
+struct KeyArgs {
     /// passphrase for (letters only) key generation
-    #[arg(short, long)]
-    passphrase: String,
+    #[arg(short, long, group = "key_source")]
+    passphrase: Option<String>,
+
+    /// read the passphrase from the first line of a file
+    #[arg(long, value_name = "FILE", group = "key_source")]
+    passphrase_file: Option<PathBuf>,
+
+    /// read the passphrase from an environment variable
+    #[arg(long, value_name = "VAR", group = "key_source")]
+    passphrase_env: Option<String>,
+
+    /// key deck (all 54 cards, e.g. "AH 2H .. FA FB") to use instead of a passphrase
+    #[arg(long, group = "key_source")]
+    deck: Option<String>,
+
+    /// read the key deck from a file (cards separated by spaces or line breaks)
+    #[arg(long, value_name = "FILE", group = "key_source")]
+    deck_file: Option<PathBuf>,
+
+    /// use the last two passphrase letters to place the jokers (Schneier's "Optional step")
+    #[arg(long, conflicts_with_all = ["deck", "deck_file"])]
+    optional_step: bool,
+}
+
+/// How the plaintext is padded out to a multiple of 5 letters
+#[derive(Args)]
+struct PadArgs {
+    /// padding scheme - marked ends the padding with a letter A-E giving its length so it can be
+    /// stripped exactly, as the escape encoding needs
+    #[arg(long, value_enum, default_value_t = PadScheme::Letter)]
+    padding: PadScheme,
+
+    /// letter used for padding, not the escape letter with the escape encoding
+    #[arg(long, value_name = "LETTER", default_value = "X", value_parser = parse_letter)]
+    pad_letter: UpperLetter,
+}
+
+impl PadArgs {
+    fn padding(&self) -> Padding {
+        match self.padding {
+            PadScheme::Letter => Padding::Letter(self.pad_letter),
+            PadScheme::Marked => Padding::Marked(self.pad_letter),
+            PadScheme::None => Padding::None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum PadScheme {
+    Letter,
+    Marked,
+    None,
+}
+
+/// How text other than letters is turned into letters
+#[derive(Args)]
+struct EncodingArgs {
+    /// letters drops everything else, escape encodes digits, spaces and punctuation as the escape
+    /// letter followed by a code letter
+    #[arg(long, value_enum, default_value_t = EncodingScheme::Letters)]
+    encoding: EncodingScheme,
+
+    /// escape letter for the escape encoding
+    #[arg(long, value_name = "LETTER", default_value = "Z", value_parser = parse_letter)]
+    escape_letter: UpperLetter,
+}
+
+impl EncodingArgs {
+    fn encoding(&self) -> Encoding {
+        match self.encoding {
+            EncodingScheme::Letters => Encoding::Letters,
+            EncodingScheme::Escape => Encoding::Escape(self.escape_letter),
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum EncodingScheme {
+    Letters,
+    Escape,
+}
+
+/// How the ciphertext or keystream letters are laid out in groups and lines
+#[derive(Args)]
+struct LayoutArgs {
+    /// letters in each group, 0 for no groups
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    group_size: usize,
+
+    /// groups on each line, 0 for all on one line
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    groups_per_line: usize,
+
+    /// number each line with its line number or the number of its first group
+    #[arg(long, value_enum, default_value_t = LineNumbering::None)]
+    numbering: LineNumbering,
+
+    /// radiogram layout with an "NR <NUMBER> GR <GROUPS>" header line and a final "AR" line
+    #[arg(long, value_name = "NUMBER", num_args = 0..=1, default_missing_value = "1")]
+    radiogram: Option<u32>,
 }
 
+impl LayoutArgs {
+    fn group_format(&self) -> GroupFormat {
+        GroupFormat {
+            group_size: self.group_size,
+            groups_per_line: self.groups_per_line,
+            numbering: match self.numbering {
+                LineNumbering::None => Numbering::None,
+                LineNumbering::Lines => Numbering::Lines,
+                LineNumbering::Groups => Numbering::Groups,
+            },
+            radiogram: self.radiogram,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LineNumbering {
+    None,
+    Lines,
+    Groups,
+}
+
+/// Per-message key indicator sent at the start of the ciphertext
 #[derive(Args)]
-#[group(required = true, multiple = false)]
-struct Cmd {
-    /// Encrypt stdin with keystream generated from passphrase
-    #[arg(short, long)] //
-    encrypt: bool,
-    /// Decrypt stdin with keystream generated from passphrase
-    #[arg(short, long)]
-    decrypt: bool,
+struct IndicatorArgs {
+    /// start the ciphertext with a message indicator which further keys the deck (a keystream per message)
+    #[arg(long)]
+    indicator: bool,
+
+    /// letters in the message indicator
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_INDICATOR_LENGTH, requires = "indicator",
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    indicator_length: usize,
+
+    /// send the message indicator as is or encrypted with the unmodified key deck
+    #[arg(long, value_name = "MODE", value_enum, default_value_t = IndicatorSending::Clear, requires = "indicator")]
+    indicator_mode: IndicatorSending,
+}
+
+impl IndicatorArgs {
+    fn indicator(&self, letters: Option<MessageIndicator>) -> Option<IndicatorOptions> {
+        self.indicator.then(|| IndicatorOptions {
+            // --indicator-letters conflicts with --indicator-length so only overrides the default
+            length: letters.as_ref().map_or(self.indicator_length, |l| l.len()),
+            mode: match self.indicator_mode {
+                IndicatorSending::Clear => IndicatorMode::Clear,
+                IndicatorSending::Encrypted => IndicatorMode::Encrypted,
+            },
+            letters,
+        })
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum IndicatorSending {
+    Clear,
+    Encrypted,
+}
+
+struct IndicatorOptions {
+    length: usize,
+    mode: IndicatorMode,
+    // random if None
+    letters: Option<MessageIndicator>,
+}
+
+// How the text is turned into letters, padded and laid out
+struct TextOptions {
+    padding: Padding,
+    encoding: Encoding,
+    layout: GroupFormat,
+    lenient: bool,
+    indicator: Option<IndicatorOptions>,
+}
+
+fn parse_letter(s: &str) -> Result<UpperLetter, String> {
+    match s.to_uppercase().as_bytes() {
+        [letter] => UpperLetter::new(*letter),
+        _ => None,
+    }
+    .ok_or_else(|| "must be a single letter A-Z".to_string())
+}
+
+fn parse_indicator(s: &str) -> Result<MessageIndicator, String> {
+    match MessageIndicator::from_str(s) {
+        Ok(indicator) if indicator.is_empty() => Err("must be at least one letter".to_string()),
+        Ok(indicator) => Ok(indicator),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TraceFormat {
+    Text,
+    Json,
 }
 
 #[derive(Debug)]
@@ -65,6 +492,44 @@ impl Display for IllegalArgumentFormatError {
 }
 impl std::error::Error for IllegalArgumentFormatError {}
 
+#[derive(Debug)]
+struct EmptyPassphraseError;
+impl Display for EmptyPassphraseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "EmptyPassphraseError - the passphrase has no letters so wouldn't key the deck"
+        )
+    }
+}
+impl std::error::Error for EmptyPassphraseError {}
+
+#[derive(Debug)]
+struct LetterPaddingWithEscapeError;
+impl Display for LetterPaddingWithEscapeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "LetterPaddingWithEscapeError - the escape encoding needs --padding marked (or none), \
+             letter padding can't be told apart from escape codes"
+        )
+    }
+}
+impl std::error::Error for LetterPaddingWithEscapeError {}
+
+#[derive(Debug)]
+struct PadLetterIsEscapeError;
+impl Display for PadLetterIsEscapeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "PadLetterIsEscapeError - the pad letter can't be the escape letter, the padding would \
+             decode as an unfinished escape"
+        )
+    }
+}
+impl std::error::Error for PadLetterIsEscapeError {}
+
 #[derive(Debug)]
 struct IllegalInputFormatError;
 impl Display for IllegalInputFormatError {
@@ -74,55 +539,499 @@ impl Display for IllegalInputFormatError {
 }
 impl std::error::Error for IllegalInputFormatError {}
 
-fn remove_whitespace(s: &mut String) {
-    s.retain(|c| !c.is_whitespace());
+// The passphrase text from whichever source was given, prompting for it (without echo) on the
+// terminal if none was.  Wiped from memory when dropped.
+fn passphrase_text(key: &mut KeyArgs) -> Result<Zeroizing<String>> {
+    let mut text = if let Some(text) = key.passphrase.take() {
+        Zeroizing::new(text)
+    } else if let Some(path) = &key.passphrase_file {
+        Zeroizing::new(
+            fs::read_to_string(path)
+                .with_context(|| format!("unable to read passphrase file {}", path.display()))?,
+        )
+    } else if let Some(var) = &key.passphrase_env {
+        Zeroizing::new(
+            env::var(var).map_err(|e| anyhow!("unable to read passphrase from {}: {}", var, e))?,
+        )
+    } else {
+        Zeroizing::new(rpassword::prompt_password("Passphrase: ").context(
+            "unable to prompt for passphrase - try --passphrase-file or --passphrase-env",
+        )?)
+    };
+    // only the first line, without its line ending, is the passphrase
+    if let Some(end) = text.find(['\r', '\n']) {
+        text.truncate(end);
+    }
+    Ok(text)
+}
+
+// The key deck text from whichever deck option was given, None if neither was.  Wiped from
+// memory when dropped.
+fn deck_text(key: &mut KeyArgs) -> Result<Option<Zeroizing<String>>> {
+    if let Some(text) = key.deck.take() {
+        Ok(Some(Zeroizing::new(text)))
+    } else if let Some(path) = &key.deck_file {
+        Ok(Some(Zeroizing::new(
+            fs::read_to_string(path)
+                .with_context(|| format!("unable to read deck file {}", path.display()))?,
+        )))
+    } else {
+        Ok(None)
+    }
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
-    let (enc, dec) = ((&cli.cmd).encrypt, (&cli.cmd).decrypt);
+// The deck as card names or, if numeric, default values.  Wiped from memory when dropped.
+fn deck_display(deck: &Cards, numeric: bool) -> Zeroizing<String> {
+    Zeroizing::new(if numeric {
+        deck.by_def_raw_values()
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<String>>()
+            .join(" ")
+    } else {
+        deck.to_string()
+    })
+}
 
-    let encrypting = match (enc, dec) {
-        (true, _) => true,
-        (_, true) => false,
-        _ => unreachable!(),
-    };
+fn keygen(numeric: bool, seed: Option<u64>, output: Option<&PathBuf>) -> Result<()> {
+    // can panic if code broken - next line uses illegal joker count
+    let mut deck = Cards::new(1, JokersPerDeck::new(2).unwrap());
+    match seed {
+        Some(seed) => deck.shuffle_fy_with(&mut ChaCha20Rng::seed_from_u64(seed)),
+        None => deck.shuffle_fy(),
+    }
+    let text = deck_display(&deck, numeric);
+    match output {
+        Some(path) => fs::write(path, format!("{}\n", *text))
+            .with_context(|| format!("unable to write deck file {}", path.display()))?,
+        None => println!("{}", *text),
+    }
+    Ok(())
+}
 
-    let mut stdin = io::read_to_string(io::stdin())?;
-    remove_whitespace(&mut stdin);
+fn key_stream(key_deck: Cards, len: usize, worksheet: Option<&mut Worksheet>) -> KeyStream {
+    match worksheet {
+        Some(worksheet) => {
+            let (ks, trace) = trace_key_stream(key_deck, len);
+            worksheet.key_stream = trace;
+            ks
+        }
+        None => KeystreamGenerator::new(key_deck).key_stream(len),
+    }
+}
 
-    let passphrase = match Passphrase::from_str(&cli.passphrase) {
+// The key deck further keyed with the message indicator, recording the KeyStream encrypting the
+// indicator (if sent encrypted) and the keying sub-steps in the worksheet if tracing
+fn indicator_key_deck(
+    letters: &MessageIndicator,
+    mode: IndicatorMode,
+    key_deck: Cards,
+    worksheet: Option<&mut Worksheet>,
+) -> Cards {
+    match worksheet {
+        Some(worksheet) => {
+            if mode == IndicatorMode::Encrypted {
+                let (_, trace) = trace_key_stream(key_deck.clone(), letters.len());
+                worksheet.indicator_key_stream = trace;
+            }
+            let (key_deck, keying) = trace_indicator_key_deck(letters, key_deck);
+            worksheet.indicator_keying = keying;
+            key_deck
+        }
+        None => letters.key_deck(key_deck),
+    }
+}
+
+fn worksheet_json(worksheet: &Worksheet) -> serde_json::Value {
+    let entries = |trace: &[TraceEntry]| -> Vec<serde_json::Value> {
+        trace
+            .iter()
+            .map(|entry| match entry.sub_step {
+                SubStep::Output(output) => json!({
+                    "step": entry.step,
+                    "sub_step": "Output",
+                    "card": output.card.to_string(),
+                    "value": u8::from(output.value),
+                    "letter": output.letter.map(|l| (u8::from(l) as char).to_string()),
+                }),
+                sub_step => json!({
+                    "step": entry.step,
+                    "sub_step": sub_step.to_string(),
+                    "deck": entry.deck.to_string(),
+                }),
+            })
+            .collect()
+    };
+    json!({
+        "keying": entries(&worksheet.keying),
+        "indicator_key_stream": entries(&worksheet.indicator_key_stream),
+        "indicator_keying": entries(&worksheet.indicator_keying),
+        "key_stream": entries(&worksheet.key_stream),
+    })
+}
+
+// The key deck from whichever key source was given, recording the keying sub-steps in the
+// worksheet if tracing
+fn key_deck(key: &mut KeyArgs, worksheet: Option<&mut Worksheet>) -> Result<Cards> {
+    if let Some(text) = deck_text(key)? {
+        return Ok(key_deck_from_str(&text)?);
+    }
+    let passphrase = match Passphrase::from_str(&passphrase_text(key)?) {
         Ok(passphrase) => passphrase,
         Err(e) => {
             eprintln!("{}", e);
             return Err(IllegalArgumentFormatError.into());
         }
     };
+    // no letters leaves the deck in the public unkeyed order
+    if passphrase.letters().is_empty() {
+        return Err(EmptyPassphraseError.into());
+    }
+    let options = KeyingOptions {
+        optional_step: key.optional_step,
+    };
+    Ok(match worksheet {
+        Some(worksheet) => {
+            let (key_deck, keying) = trace_key_deck_from_passphrase(&passphrase, &options);
+            worksheet.keying = keying;
+            key_deck
+        }
+        None => key_deck_from_passphrase_with_options(&passphrase, &options),
+    })
+}
 
-    let key_deck = key_deck_from_passphrase(&passphrase);
+fn print_worksheet(worksheet: &Worksheet, trace: Option<TraceFormat>) {
+    match trace {
+        Some(TraceFormat::Text) => eprint!("{}", worksheet),
+        Some(TraceFormat::Json) => eprintln!("{}", worksheet_json(worksheet)),
+        None => {}
+    }
+}
+
+// Encrypt encoding the text, adding the padding, keying the deck with the message indicator and
+// laying out the ciphertext, or decrypt (strictly unless lenient) removing the layout, keying the
+// deck with the message indicator, removing the padding and decoding the text
+fn crypt(
+    mut key: KeyArgs,
+    trace: Option<TraceFormat>,
+    encrypting: bool,
+    options: TextOptions,
+) -> Result<()> {
+    let TextOptions {
+        padding,
+        encoding,
+        layout,
+        lenient,
+        indicator,
+    } = options;
+    if let Encoding::Escape(escape) = encoding {
+        match padding {
+            // every letter but the escape letter is a code letter so can't be told from padding
+            Padding::Letter(_) => return Err(LetterPaddingWithEscapeError.into()),
+            // padding is only stripped on request, unstripped escape letters can't be decoded
+            Padding::Marked(pad) if pad == escape => return Err(PadLetterIsEscapeError.into()),
+            _ => {}
+        }
+    }
+    // key first so any passphrase prompt appears before stdin is waited on
+    let mut worksheet = Worksheet::default();
+    let mut key_deck = key_deck(&mut key, trace.map(|_| &mut worksheet))?;
+
+    let stdin = io::read_to_string(io::stdin())?;
 
     let output = if encrypting {
-        let pt = match PlainText::from_str(&stdin) {
-            Ok(pt) => pt,
-            Err(e) => {
-                eprintln!("{}", e);
-                return Err(IllegalInputFormatError.into());
-            }
-        };
-        let ks = get_key_stream(key_deck, pt.len());
-        encrypt(&pt, &ks).to_string()
+        let transliteration = transliterate(&stdin);
+        for unmapped in transliteration.unmapped.iter() {
+            eprintln!("warning: unable to transliterate {}", unmapped);
+        }
+        // leading and trailing whitespace (e.g. the final line ending) isn't part of the message
+        let mut pt = encoding.encode(transliteration.text.trim());
+        pt.pad(padding);
+        let mut ct = CypherText::new();
+        if let Some(indicator) = indicator {
+            let letters = indicator
+                .letters
+                .unwrap_or_else(|| MessageIndicator::random(indicator.length));
+            ct.0 = letters.to_group(&key_deck, indicator.mode);
+            key_deck = indicator_key_deck(
+                &letters,
+                indicator.mode,
+                key_deck,
+                trace.map(|_| &mut worksheet),
+            );
+        }
+        let ks = key_stream(key_deck, pt.len(), trace.map(|_| &mut worksheet));
+        ct.0.extend(try_encrypt(&pt, &ks)?.0);
+        layout.format(&ct.0)
     } else {
-        let ct = match CypherText::from_str(&stdin) {
-            Ok(ct) => ct,
-            Err(e) => {
-                eprintln!("{}", e);
-                return Err(IllegalInputFormatError.into());
+        let header_groups = layout.header_groups(&stdin);
+        let stdin = layout.unformat(&stdin);
+        let groups = stdin.split_whitespace().count();
+        if header_groups.is_some_and(|header| header != groups) {
+            eprintln!(
+                "warning: radiogram header gives {} groups but {} received - groups may be lost or extra",
+                header_groups.unwrap_or_default(),
+                groups
+            );
+        }
+        let mut ct = if lenient {
+            let Ok(ct) = CypherText::from_str(&stdin);
+            ct
+        } else {
+            match CypherText::from_str_strict(&stdin) {
+                Ok(ct) => ct,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return Err(IllegalInputFormatError.into());
+                }
             }
         };
-        let ks = get_key_stream(key_deck, ct.len());
-        decrypt(&ct, &ks).to_string()
+        if !ct.is_whole_groups() {
+            eprintln!(
+                "warning: ciphertext length {} is not a multiple of 5 - letters may be missing",
+                ct.len()
+            );
+        }
+        if let Some(indicator) = indicator {
+            if ct.len() < indicator.length {
+                return Err(CypherError::MissingIndicator {
+                    needed: indicator.length,
+                    available: ct.len(),
+                }
+                .into());
+            }
+            let group: Vec<UpperLetter> = ct.0.drain(..indicator.length).collect();
+            let letters = MessageIndicator::from_group(&group, &key_deck, indicator.mode);
+            key_deck = indicator_key_deck(
+                &letters,
+                indicator.mode,
+                key_deck,
+                trace.map(|_| &mut worksheet),
+            );
+        }
+        let ks = key_stream(key_deck, ct.len(), trace.map(|_| &mut worksheet));
+        let mut pt = try_decrypt(&ct, &ks)?;
+        pt.strip_padding(padding)?;
+        encoding.decode(&pt)?
     };
 
+    print_worksheet(&worksheet, trace);
     println!("{}", output);
     Ok(())
 }
+
+#[derive(Debug)]
+struct SelftestFailedError(usize);
+impl Display for SelftestFailedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "SelftestFailedError - {} test vector(s) failed", self.0)
+    }
+}
+impl std::error::Error for SelftestFailedError {}
+
+// Run each of the test vectors printing the differences for any that fail
+fn selftest(file: Option<&PathBuf>, optional_step: bool) -> Result<()> {
+    let vectors = match file {
+        Some(path) => fs::read_to_string(path)
+            .with_context(|| format!("unable to read test vector file {}", path.display()))?,
+        None => TEST_VECTORS.to_string(),
+    };
+    let results = vectors::run_test_vectors(&vectors, &KeyingOptions { optional_step })?;
+    let failed = results.iter().filter(|r| !r.passed()).count();
+    for result in results.iter() {
+        println!("{}", result);
+    }
+    println!("{} passed, {} failed", results.len() - failed, failed);
+    if failed > 0 {
+        return Err(SelftestFailedError(failed).into());
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+struct DepthFoundError(usize);
+impl Display for DepthFoundError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "DepthFoundError - {} pair(s) of messages likely in depth",
+            self.0
+        )
+    }
+}
+impl std::error::Error for DepthFoundError {}
+
+// Measure every pair of ciphertext files in the directory printing those likely in depth, or all
+// of them
+fn analyze_depth(
+    dir: &PathBuf,
+    threshold: f64,
+    all: bool,
+    indicator_length: Option<usize>,
+    layout: GroupFormat,
+) -> Result<()> {
+    let mut paths = Vec::new();
+    for entry in
+        fs::read_dir(dir).with_context(|| format!("unable to read directory {}", dir.display()))?
+    {
+        let path = entry?.path();
+        if path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    let mut cts = Vec::with_capacity(paths.len());
+    for path in paths.iter() {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("unable to read ciphertext file {}", path.display()))?;
+        match CypherText::from_str_strict(&layout.unformat(&text)) {
+            // messages are compared aligned from their first letters so only after the indicator
+            Ok(mut ct) => {
+                if let Some(needed) = indicator_length {
+                    if ct.len() < needed {
+                        return Err(anyhow::Error::new(CypherError::MissingIndicator {
+                            needed,
+                            available: ct.len(),
+                        })
+                        .context(format!("ciphertext file {}", path.display())));
+                    }
+                    ct.0.drain(..needed);
+                }
+                cts.push(ct)
+            }
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                return Err(IllegalInputFormatError.into());
+            }
+        }
+    }
+
+    let name = |i: usize| {
+        paths[i]
+            .file_name()
+            .map_or_else(String::new, |n| n.to_string_lossy().into())
+    };
+    let width = (0..paths.len()).map(|i| name(i).len()).max().unwrap_or(0);
+    let depths = depth::measure_depths(&cts);
+    let found = depths.iter().filter(|d| d.in_depth(threshold)).count();
+    for pair in depths.iter().filter(|d| all || d.in_depth(threshold)) {
+        println!(
+            "{:<5}  {:<width$}  {:<width$}  {}",
+            if pair.in_depth(threshold) {
+                "DEPTH"
+            } else {
+                "ok"
+            },
+            name(pair.first),
+            name(pair.second),
+            pair
+        );
+    }
+    println!(
+        "{} messages, {} pairs, {} likely in depth",
+        cts.len(),
+        depths.len(),
+        found
+    );
+    if found > 0 {
+        return Err(DepthFoundError(found).into());
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    match Cli::parse().command {
+        Command::Encrypt {
+            key,
+            pad,
+            encoding,
+            layout,
+            indicator,
+            indicator_letters,
+            no_pad,
+            trace,
+        } => {
+            let options = TextOptions {
+                padding: if no_pad { Padding::None } else { pad.padding() },
+                encoding: encoding.encoding(),
+                layout: layout.group_format(),
+                lenient: false,
+                indicator: indicator.indicator(indicator_letters),
+            };
+            crypt(key, trace, true, options)
+        }
+        Command::Decrypt {
+            key,
+            pad,
+            encoding,
+            layout,
+            indicator,
+            strip_pad,
+            lenient,
+            trace,
+        } => {
+            let options = TextOptions {
+                padding: if strip_pad {
+                    pad.padding()
+                } else {
+                    Padding::None
+                },
+                encoding: encoding.encoding(),
+                layout: layout.group_format(),
+                lenient,
+                indicator: indicator.indicator(None),
+            };
+            crypt(key, trace, false, options)
+        }
+        Command::Keystream {
+            length,
+            mut key,
+            layout,
+        } => {
+            let mut generator = KeystreamGenerator::new(key_deck(&mut key, None)?);
+            let ks = generator.key_stream(length);
+            println!("{}", layout.group_format().format(&ks.0));
+            Ok(())
+        }
+        Command::Keydeck {
+            mut key,
+            numeric,
+            trace,
+        } => {
+            let mut worksheet = Worksheet::default();
+            let key_deck = key_deck(&mut key, trace.map(|_| &mut worksheet))?;
+            print_worksheet(&worksheet, trace);
+            println!("{}", *deck_display(&key_deck, numeric));
+            Ok(())
+        }
+        Command::Selftest {
+            file,
+            optional_step,
+        } => selftest(file.as_ref(), optional_step),
+        Command::Keygen {
+            numeric,
+            seed,
+            output,
+        } => keygen(numeric, seed, output.as_ref()),
+        Command::Stats { samples, length } => {
+            println!("{}", stats::sample_key_stream_stats(samples, length)?);
+            Ok(())
+        }
+        Command::Analyze { command } => match command {
+            AnalyzeCommand::Depth {
+                dir,
+                threshold,
+                all,
+                indicator,
+                indicator_length,
+                layout,
+            } => analyze_depth(
+                &dir,
+                threshold,
+                all,
+                indicator.then_some(indicator_length),
+                layout.group_format(),
+            ),
+        },
+    }
+}
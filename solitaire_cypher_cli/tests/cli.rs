@@ -5,17 +5,17 @@ mod tests {
 
     #[test]
     fn test_no_args() {
-        let mut cmd =
-            Command::cargo_bin("solitaire_cypher").expect("crate binary should be where expected");
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
         cmd.assert().failure().stderr(predicate::str::contains(
-            "required arguments were not provided",
+            "Usage: solitaire_cypher_cli <COMMAND>",
         ));
     }
 
     #[test]
     fn test_garbage_args() {
-        let mut cmd =
-            Command::cargo_bin("solitaire_cypher").expect("crate binary should be where expected");
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
         cmd.arg("--moosepoop");
         cmd.assert()
             .failure()
@@ -24,10 +24,10 @@ mod tests {
 
     #[test]
     fn test_illegal_arg_combo() {
-        let mut cmd =
-            Command::cargo_bin("solitaire_cypher").expect("crate binary should be where expected");
-        cmd.arg("--encrypt")
-            .arg("--decrypt")
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("decrypt")
             .arg("--passphrase moosepoop")
             .write_stdin("SOMETEXT");
         cmd.assert()
@@ -37,11 +37,11 @@ mod tests {
 
     #[test]
     fn test_unparsable_passphrase() {
-        let mut cmd =
-            Command::cargo_bin("solitaire_cypher").expect("crate binary should be where expected");
-        cmd.arg("--passphrase")
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
             .arg("cryp%^&omicon")
-            .arg("--encrypt")
             .write_stdin("SOLITAIRE");
         cmd.assert()
             .failure()
@@ -50,23 +50,1225 @@ mod tests {
 
     #[test]
     fn test_encrypt_happypath() {
-        let mut cmd =
-            Command::cargo_bin("solitaire_cypher").expect("crate binary should be where expected");
-        cmd.arg("--passphrase")
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
             .arg("cryptonomicon")
-            .arg("--encrypt")
             .write_stdin("SOLITAIRE");
         cmd.assert().success().stdout("KIRAK SFJAN\n");
     }
 
     #[test]
     fn test_decrypt_happypath() {
-        let mut cmd =
-            Command::cargo_bin("solitaire_cypher").expect("crate binary should be where expected");
-        cmd.arg("--decrypt")
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .write_stdin("KIRAK SFJAN");
+        cmd.assert().success().stdout("SOLITAIREX\n");
+    }
+
+    #[test]
+    fn test_marked_padding_round_trip() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--padding")
+            .arg("marked")
+            .write_stdin("SOLITAIRE");
+        cmd.assert().success().stdout("KIRAK SFJAQ\n");
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--padding")
+            .arg("marked")
+            .arg("--strip-pad")
+            .write_stdin("KIRAK SFJAQ");
+        cmd.assert().success().stdout("SOLITAIRE\n");
+    }
+
+    #[test]
+    fn test_marked_padding_missing() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--padding")
+            .arg("marked")
+            .arg("--strip-pad")
+            .write_stdin("KIRAK SFJAN");
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("expected padding"));
+    }
+
+    #[test]
+    fn test_strip_pad_letter() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--strip-pad")
+            .write_stdin("KIRAK SFJAN");
+        cmd.assert().success().stdout("SOLITAIRE\n");
+    }
+
+    #[test]
+    fn test_no_pad() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--no-pad")
+            .write_stdin("SOLITAIRE");
+        cmd.assert().success().stdout("KIRAK SFJA\n");
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--no-pad")
+            .arg("--padding")
+            .arg("marked");
+        cmd.assert().failure();
+    }
+
+    #[test]
+    fn test_pad_letter() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--pad-letter")
+            .arg("q")
+            .write_stdin("SOLITAIRE");
+        cmd.assert().success().stdout("KIRAK SFJAG\n");
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--pad-letter")
+            .arg("QQ");
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("single letter"));
+    }
+
+    #[test]
+    fn test_escape_encoding_round_trip() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--encoding")
+            .arg("escape")
+            .arg("--padding")
+            .arg("marked")
+            .write_stdin("Meet at 0930\n");
+        cmd.assert().success().stdout("EYKLQ CXLVA QMWXG LOBCQ\n");
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--encoding")
+            .arg("escape")
+            .arg("--padding")
+            .arg("marked")
+            .arg("--strip-pad")
+            .write_stdin("EYKLQ CXLVA QMWXG LOBCQ");
+        cmd.assert().success().stdout("MEET AT 0930\n");
+    }
+
+    #[test]
+    fn test_escape_letter() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--encoding")
+            .arg("escape")
+            .arg("--escape-letter")
+            .arg("j")
+            .write_stdin("RDTBC BH");
+        cmd.assert().success().stdout("Z, 9\n");
+    }
+
+    #[test]
+    fn test_escape_unfinished() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--encoding")
+            .arg("escape")
+            .write_stdin("SVF");
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("unfinished escape"));
+    }
+
+    #[test]
+    fn test_escape_pad_letter() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--encoding")
+            .arg("escape")
+            .arg("--padding")
+            .arg("marked")
+            .arg("--pad-letter")
+            .arg("Z")
+            .write_stdin("Meet at 0930\n");
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("PadLetterIsEscapeError"));
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--encoding")
+            .arg("escape")
+            .arg("--escape-letter")
+            .arg("Q")
+            .arg("--padding")
+            .arg("marked")
+            .arg("--pad-letter")
+            .arg("q")
+            .write_stdin("Meet at 0930\n");
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("PadLetterIsEscapeError"));
+        // without padding there is nothing to mistake for an escape
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--encoding")
+            .arg("escape")
+            .arg("--padding")
+            .arg("none")
+            .write_stdin("Meet at 0930\n");
+        cmd.assert().success();
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--encoding")
+            .arg("escape")
+            .arg("--escape-letter")
+            .arg("X")
+            .arg("--no-pad")
+            .write_stdin("Meet at 0930\n");
+        cmd.assert().success();
+    }
+
+    #[test]
+    fn test_escape_letter_padding() {
+        // "+" is ZX, so X padding couldn't be told apart from its code letter
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("foo")
+            .arg("--encoding")
+            .arg("escape")
+            .write_stdin("C+\n");
+        cmd.assert()
+            .failure()
+            .stdout("")
+            .stderr(predicate::str::contains("LetterPaddingWithEscapeError"));
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("foo")
+            .arg("--encoding")
+            .arg("escape")
+            .arg("--strip-pad")
+            .write_stdin("KSEWR\n");
+        cmd.assert()
+            .failure()
+            .stdout("")
+            .stderr(predicate::str::contains("LetterPaddingWithEscapeError"));
+    }
+
+    #[test]
+    fn test_escape_marked_padding_round_trip() {
+        // a message ending in a symbol whose code letter is the pad letter
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        let output = cmd
+            .arg("encrypt")
+            .arg("--passphrase")
+            .arg("foo")
+            .arg("--encoding")
+            .arg("escape")
+            .arg("--padding")
+            .arg("marked")
+            .write_stdin("C+\n")
+            .output()
+            .expect("should run");
+        assert!(output.status.success());
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("foo")
+            .arg("--encoding")
+            .arg("escape")
+            .arg("--padding")
+            .arg("marked")
+            .arg("--strip-pad")
+            .write_stdin(output.stdout);
+        cmd.assert().success().stdout("C+\n");
+    }
+
+    #[test]
+    fn test_transliteration() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .write_stdin("Café Müller ★");
+        cmd.assert()
+            .success()
+            .stdout("UULWD MIDAH\n")
+            .stderr(predicate::str::contains(
+                "unable to transliterate '★' (U+2605) at position 12",
+            ));
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("Straße")
+            .write_stdin("X");
+        cmd.assert().success().stdout("AXBST\n");
+    }
+
+    #[test]
+    fn test_decrypt_strict() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .write_stdin("KIR4K SF!AN");
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "invalid symbols: '4' at position 3, '!' at position 8",
+        ));
+    }
+
+    #[test]
+    fn test_decrypt_lenient() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--lenient")
+            .write_stdin("KIRAK-SFJAN");
+        cmd.assert()
+            .success()
+            .stdout("SOLITAIREX\n")
+            .stderr(predicate::str::is_empty());
+    }
+
+    #[test]
+    fn test_decrypt_partial_group_warning() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .write_stdin("KIRAK SFJA");
+        cmd.assert()
+            .success()
+            .stdout("SOLITAIRE\n")
+            .stderr(predicate::str::contains(
+                "ciphertext length 9 is not a multiple of 5",
+            ));
+    }
+
+    #[test]
+    fn test_keystream_layout() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("keystream")
+            .arg("60")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--groups-per-line")
+            .arg("5")
+            .arg("--numbering")
+            .arg("groups")
+            .arg("--radiogram")
+            .arg("7");
+        cmd.assert().success().stdout(concat!(
+            "NR 7 GR 12\n",
+            " 1  RTFRQ RWRVP QLWNG HOAEO WZQXP\n",
+            " 6  IKFFJ DXQVK IKEAA AGMDZ EVBBB\n",
+            "11  WOYOF IEVIW\n",
+            "AR\n",
+        ));
+    }
+
+    #[test]
+    fn test_layout_round_trip() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--group-size")
+            .arg("4")
+            .arg("--groups-per-line")
+            .arg("2")
+            .arg("--numbering")
+            .arg("lines")
+            .arg("--radiogram")
+            .write_stdin("SOLITAIRE");
+        cmd.assert()
+            .success()
+            .stdout("NR 1 GR 3\n1  KIRA KSFJ\n2  AN\nAR\n");
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--numbering")
+            .arg("lines")
+            .arg("--radiogram")
+            .write_stdin("NR 1 GR 3\n1  KIRA KSFJ\n2  AN\nAR\n");
+        cmd.assert().success().stdout("SOLITAIREX\n");
+        // the layout has to be given to decrypt strictly
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .write_stdin("NR 1 GR 3\n1  KIRA KSFJ\n2  AN\nAR\n");
+        cmd.assert().failure();
+    }
+
+    #[test]
+    fn test_radiogram_group_count() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--radiogram")
+            .write_stdin("NR 1 GR 2\nKIRAK SFJAN\nAR\n");
+        cmd.assert().success().stdout("SOLITAIREX\n").stderr("");
+        // a group lost in transmission
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--radiogram")
+            .write_stdin("NR 1 GR 3\nKIRAK SFJAN\nAR\n");
+        cmd.assert()
+            .success()
+            .stdout("SOLITAIREX\n")
+            .stderr(predicate::str::contains(
+                "radiogram header gives 3 groups but 2 received",
+            ));
+    }
+
+    #[test]
+    fn test_indicator_round_trip() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--indicator")
+            .arg("--indicator-letters")
+            .arg("AAAAB")
+            .write_stdin("SOLITAIRE");
+        cmd.assert().success().stdout("AAAAB FTERY AKBIZ\n");
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--indicator")
+            .write_stdin("AAAAB FTERY AKBIZ");
+        cmd.assert().success().stdout("SOLITAIREX\n");
+    }
+
+    #[test]
+    fn test_indicator_encrypted() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--indicator")
+            .arg("--indicator-mode")
+            .arg("encrypted")
+            .arg("--indicator-letters")
+            .arg("AAAAB")
+            .write_stdin("SOLITAIRE");
+        cmd.assert().success().stdout("SUGSS FTERY AKBIZ\n");
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--indicator")
+            .arg("--indicator-mode")
+            .arg("encrypted")
+            .write_stdin("SUGSS FTERY AKBIZ");
+        cmd.assert().success().stdout("SOLITAIREX\n");
+    }
+
+    #[test]
+    fn test_indicator_random() {
+        let encrypt = || {
+            let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+                .expect("crate binary should be where expected");
+            cmd.arg("encrypt")
+                .arg("--passphrase")
+                .arg("cryptonomicon")
+                .arg("--indicator")
+                .arg("--indicator-length")
+                .arg("10")
+                .write_stdin("SOLITAIRE");
+            let output = cmd.output().expect("should run");
+            assert!(output.status.success());
+            String::from_utf8(output.stdout).expect("should be utf8")
+        };
+        let first = encrypt();
+        assert_eq!(first.trim_end().len(), 23);
+        // the same message and key give a different ciphertext every time
+        assert_ne!(first, encrypt());
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--indicator")
+            .arg("--indicator-length")
+            .arg("10")
+            .write_stdin(first);
+        cmd.assert().success().stdout("SOLITAIREX\n");
+    }
+
+    #[test]
+    fn test_indicator_missing() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--indicator")
+            .write_stdin("AB");
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "too short for its message indicator",
+        ));
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
             .arg("--passphrase")
             .arg("cryptonomicon")
+            .arg("--indicator-letters")
+            .arg("AAAAB");
+        cmd.assert().failure();
+    }
+
+    #[test]
+    fn test_indicator_length_invalid() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--indicator")
+            .arg("--indicator-length")
+            .arg("0")
+            .write_stdin("SOLITAIRE");
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("0 is not in 1.."));
+        // the letters set the length so an explicit length could contradict them
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--indicator")
+            .arg("--indicator-length")
+            .arg("10")
+            .arg("--indicator-letters")
+            .arg("AAAAB")
+            .write_stdin("SOLITAIRE");
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("cannot be used with"));
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--indicator")
+            .arg("--indicator-letters")
+            .arg("")
+            .write_stdin("SOLITAIRE");
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("must be at least one letter"));
+    }
+
+    #[test]
+    fn test_optional_step_happypath() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--optional-step")
+            .write_stdin("SOLITAIRE");
+        cmd.assert().success().stdout("IDNRM ZGROJ\n");
+    }
+
+    #[test]
+    fn test_trace_text() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--trace")
+            .arg("text")
+            .write_stdin("SOLITAIRE");
+        cmd.assert()
+            .success()
+            .stdout("KIRAK SFJAN\n")
+            .stderr(predicate::str::contains("Keying"))
+            .stderr(predicate::str::contains("  13 Letter cut N (14)"))
+            .stderr(predicate::str::contains("Key stream"));
+    }
+
+    #[test]
+    fn test_trace_json() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--trace")
+            .arg("json")
+            .write_stdin("KIRAK SFJAN");
+        cmd.assert()
+            .success()
+            .stdout("SOLITAIREX\n")
+            .stderr(predicate::str::starts_with(
+                "{\"indicator_key_stream\":[],\"indicator_keying\":[],\"key_stream\":[{\"deck\"",
+            ))
+            .stderr(predicate::str::contains("\"sub_step\":\"Output\""));
+    }
+
+    #[test]
+    fn test_trace_exact_length() {
+        // 9 letters unpadded are traced as 9 output letters, not rounded up to 10
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--no-pad")
+            .arg("--trace")
+            .arg("text")
+            .write_stdin("SOLITAIRE");
+        let output = cmd.output().unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "KIRAK SFJA\n");
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert_eq!(stderr.matches(" -> ").count(), 9);
+    }
+
+    #[test]
+    fn test_trace_indicator() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--indicator")
+            .arg("--indicator-letters")
+            .arg("AAAAB")
+            .arg("--indicator-mode")
+            .arg("encrypted")
+            .arg("--trace")
+            .arg("text")
+            .write_stdin("SOLITAIRE");
+        let output = cmd.output().unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "SUGSS FTERY AKBIZ\n"
+        );
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        let headings: Vec<&str> = stderr.lines().filter(|l| !l.starts_with(' ')).collect();
+        assert_eq!(
+            headings,
+            vec![
+                "Keying",
+                "Indicator key stream",
+                "Indicator keying",
+                "Key stream"
+            ]
+        );
+        // the indicator AAAAB encrypted with the key deck's first letters RTFRQ
+        assert!(stderr.contains("   5 Output 4C (17) -> Q"));
+        assert!(stderr.contains("   5 Letter cut B (2)"));
+
+        // sent in the clear there is no indicator key stream to trace
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--indicator")
+            .arg("--trace")
+            .arg("json")
+            .write_stdin("AAAAB FTERY AKBIZ");
+        cmd.assert()
+            .success()
+            .stdout("SOLITAIREX\n")
+            .stderr(predicate::str::contains("\"indicator_key_stream\":[]"))
+            .stderr(predicate::str::contains(
+                "\"sub_step\":\"Letter cut B (2)\"",
+            ));
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("stats")
+            .arg("--samples")
+            .arg("5")
+            .arg("--length")
+            .arg("50");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("key streams: 5  letters: 250"))
+            .stdout(predicate::str::contains("repeated pairs:"));
+    }
+
+    #[test]
+    fn test_passphrase_file() {
+        let path = std::env::temp_dir().join("solitaire_cypher_cli_test_passphrase.txt");
+        std::fs::write(&path, "cryptonomicon\n").unwrap();
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase-file")
+            .arg(&path)
+            .write_stdin("SOLITAIRE");
+        cmd.assert().success().stdout("KIRAK SFJAN\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_passphrase_file_missing() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase-file")
+            .arg("no/such/passphrase/file")
+            .write_stdin("SOLITAIRE");
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("unable to read passphrase file"));
+    }
+
+    #[test]
+    fn test_passphrase_env() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .env("SOLITAIRE_TEST_KEY", "cryptonomicon")
+            .arg("--passphrase-env")
+            .arg("SOLITAIRE_TEST_KEY")
             .write_stdin("KIRAK SFJAN");
         cmd.assert().success().stdout("SOLITAIREX\n");
     }
+
+    #[test]
+    fn test_passphrase_env_non_letter() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .env("SOLITAIRE_TEST_KEY", "crypto nomicon")
+            .arg("--passphrase-env")
+            .arg("SOLITAIRE_TEST_KEY")
+            .write_stdin("KIRAK SFJAN");
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("non-letter ' ' at position 6"));
+    }
+
+    #[test]
+    fn test_passphrase_empty() {
+        let path = std::env::temp_dir().join("solitaire_cypher_cli_test_empty_passphrase.txt");
+        std::fs::write(&path, "\n").unwrap();
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase-file")
+            .arg(&path)
+            .write_stdin("SOLITAIRE");
+        cmd.assert()
+            .failure()
+            .stdout("")
+            .stderr(predicate::str::contains("EmptyPassphraseError"));
+        std::fs::remove_file(&path).unwrap();
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .env("SOLITAIRE_TEST_KEY", "")
+            .arg("--passphrase-env")
+            .arg("SOLITAIRE_TEST_KEY")
+            .write_stdin("SOLITAIRE");
+        cmd.assert()
+            .failure()
+            .stdout("")
+            .stderr(predicate::str::contains("EmptyPassphraseError"));
+        // quotes are dropped so leave no letters either
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("keystream").arg("10").arg("--passphrase").arg("''");
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("EmptyPassphraseError"));
+    }
+
+    #[test]
+    fn test_passphrase_sources_conflict() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .arg("--passphrase-env")
+            .arg("SOLITAIRE_TEST_KEY")
+            .write_stdin("SOLITAIRE");
+        cmd.assert().failure();
+    }
+
+    const NEW_DECK: &str = "AH 2H 3H 4H 5H 6H 7H 8H 9H TH JH QH KH \
+        AC 2C 3C 4C 5C 6C 7C 8C 9C TC JC QC KC \
+        KD QD JD TD 9D 8D 7D 6D 5D 4D 3D 2D AD \
+        KS QS JS TS 9S 8S 7S 6S 5S 4S 3S 2S AS FA FB";
+
+    #[test]
+    fn test_deck_happypath() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--deck")
+            .arg(NEW_DECK)
+            .write_stdin("AAAAAAAAAA");
+        cmd.assert().success().stdout("EXKYI ZSGEH\n");
+    }
+
+    #[test]
+    fn test_deck_file() {
+        let path = std::env::temp_dir().join("solitaire_cypher_cli_test_deck.txt");
+        std::fs::write(
+            &path,
+            NEW_DECK.replace(" AC", "\nAC").replace(" KD", "\r\nKD"),
+        )
+        .unwrap();
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--deck-file")
+            .arg(&path)
+            .write_stdin("EXKYI ZSGEH");
+        cmd.assert().success().stdout("AAAAAAAAAA\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_deck_missing_and_duplicate() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--deck")
+            .arg(NEW_DECK.replace("QS", "FA"))
+            .write_stdin("AAAAAAAAAA");
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "key deck is not a complete 54 card deck: missing QS; duplicate FA",
+        ));
+    }
+
+    #[test]
+    fn test_deck_unknown_card() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--deck")
+            .arg(NEW_DECK.replace("5C", "5X"))
+            .write_stdin("AAAAAAAAAA");
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "unknown card \"5X\" at position 17",
+        ));
+    }
+
+    #[test]
+    fn test_keygen_seed_reproducible() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("keygen").arg("--seed").arg("1").arg("--numeric");
+        cmd.assert().success().stdout(
+            "34 10 31 51 23 37 21 28 6 22 38 36 8 24 11 3 44 41 13 25 15 42 20 50 35 4 47 43 40 \
+            53 33 7 32 48 27 16 30 18 45 39 14 17 19 1 12 29 5 52 49 9 26 54 2 46\n",
+        );
+    }
+
+    #[test]
+    fn test_keygen_numeric_and_names_agree() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        let names = cmd.arg("keygen").arg("--seed").arg("7").output().unwrap();
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        let numbers = cmd
+            .arg("keygen")
+            .arg("--seed")
+            .arg("7")
+            .arg("--numeric")
+            .output()
+            .unwrap();
+        let encrypt = |deck: &[u8]| {
+            let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+                .expect("crate binary should be where expected");
+            cmd.arg("encrypt")
+                .arg("--deck")
+                .arg(String::from_utf8(deck.to_vec()).unwrap().trim())
+                .write_stdin("SOLITAIRE")
+                .output()
+                .unwrap()
+        };
+        let by_names = encrypt(&names.stdout);
+        assert!(by_names.status.success());
+        assert_eq!(by_names.stdout, encrypt(&numbers.stdout).stdout);
+    }
+
+    #[test]
+    fn test_keygen_output_file_keys_deck_file() {
+        let path = std::env::temp_dir().join("solitaire_cypher_cli_test_keygen.txt");
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("keygen").arg("--output").arg(&path);
+        cmd.assert().success().stdout("");
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        let ct = cmd
+            .arg("encrypt")
+            .arg("--deck-file")
+            .arg(&path)
+            .write_stdin("SOLITAIRE")
+            .output()
+            .unwrap();
+        assert!(ct.status.success());
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("decrypt")
+            .arg("--deck-file")
+            .arg(&path)
+            .write_stdin(ct.stdout);
+        cmd.assert().success().stdout("SOLITAIREX\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_keygen_rejects_key_args() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("keygen").arg("--passphrase").arg("foo");
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("unexpected argument"));
+    }
+
+    #[test]
+    fn test_keystream() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("keystream").arg("7").arg("--deck").arg(NEW_DECK);
+        cmd.assert().success().stdout("DWJXH YR\n");
+    }
+
+    #[test]
+    fn test_keydeck() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        let deck = cmd
+            .arg("keydeck")
+            .arg("--passphrase")
+            .arg("cryptonomicon")
+            .output()
+            .unwrap();
+        assert!(deck.status.success());
+        // the printed key deck keys the same as the passphrase
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("encrypt")
+            .arg("--deck")
+            .arg(String::from_utf8(deck.stdout).unwrap().trim())
+            .write_stdin("SOLITAIRE");
+        cmd.assert().success().stdout("KIRAK SFJAN\n");
+    }
+
+    #[test]
+    fn test_keydeck_numeric_trace() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("keydeck")
+            .arg("--passphrase")
+            .arg("foo")
+            .arg("--numeric")
+            .arg("--trace")
+            .arg("text");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::starts_with("22 23 24 25 26 27 28"))
+            .stderr(predicate::str::contains("   3 Letter cut O (15)"))
+            .stderr(predicate::str::contains("Key stream").not());
+    }
+
+    #[test]
+    fn test_selftest() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("selftest");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains(
+                "ok      line 55   key 'cryptonomicon'",
+            ))
+            .stdout(predicate::str::ends_with("12 passed, 0 failed\n"));
+    }
+
+    #[test]
+    fn test_selftest_file_failure() {
+        let path = std::env::temp_dir().join("solitaire_cypher_cli_test_vectors.txt");
+        std::fs::write(
+            &path,
+            "Plaintext: AAAAAAAAAAAAAAA\nKey: 'foo'\nCiphertext:  ITHZU JIWGR FARMX\n",
+        )
+        .unwrap();
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("selftest").arg("--file").arg(&path);
+        cmd.assert()
+            .failure()
+            .stdout(predicate::str::contains(
+                "Ciphertext expected ITHZU JIWGR FARMX\n           computed ITHZU JIWGR FARMW",
+            ))
+            .stdout(predicate::str::ends_with("0 passed, 1 failed\n"))
+            .stderr(predicate::str::contains("1 test vector(s) failed"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_selftest_optional_step() {
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("selftest")
+            .arg("--optional-step")
+            .arg("--file")
+            .arg("../solitaire_cypher/optional-step-regression.txt");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::ends_with("6 passed, 0 failed\n"));
+    }
+
+    const LINCOLN_FIRST: &str = "Four score and seven years ago our fathers brought forth on \
+        this continent a new nation conceived in Liberty and dedicated to the proposition that \
+        all men are created equal Now we are engaged in a great civil war testing whether that \
+        nation or any nation so conceived and so dedicated can long endure We are met on a great \
+        battlefield of that war We have come to dedicate a portion of that field as a final \
+        resting place for those who here gave their lives that that nation might live";
+    const LINCOLN_SECOND: &str = "It is for us the living rather to be dedicated here to the \
+        unfinished work which they who fought here have thus far so nobly advanced It is rather \
+        for us to be here dedicated to the great task remaining before us that from these honored \
+        dead we take increased devotion to that cause for which they gave the last full measure \
+        of devotion that we here highly resolve that these dead shall not have died in vain that \
+        this nation under God shall have a new birth of freedom";
+
+    // Write a directory of the texts each encrypted with its passphrase (and further options)
+    fn depth_dir(name: &str, messages: &[(&str, &str, &[&str])]) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).unwrap();
+        for (i, (text, passphrase, options)) in messages.iter().enumerate() {
+            let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+                .expect("crate binary should be where expected");
+            let ct = cmd
+                .arg("encrypt")
+                .arg("--passphrase")
+                .arg(passphrase)
+                .args(*options)
+                .write_stdin(*text)
+                .output()
+                .unwrap()
+                .stdout;
+            std::fs::write(dir.join(format!("msg{}.txt", i + 1)), ct).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_analyze_depth() {
+        let dir = depth_dir(
+            "solitaire_cypher_cli_test_depth",
+            &[
+                (LINCOLN_FIRST, "lincoln", &[]),
+                (LINCOLN_SECOND, "gettysburg", &["--groups-per-line", "10"]),
+                (
+                    LINCOLN_SECOND,
+                    "lincoln",
+                    &["--radiogram", "--numbering", "lines"],
+                ),
+            ],
+        );
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("analyze")
+            .arg("depth")
+            .arg(&dir)
+            .arg("--radiogram")
+            .arg("--numbering")
+            .arg("lines");
+        cmd.assert()
+            .failure()
+            .stdout(predicate::str::starts_with(
+                "DEPTH  msg1.txt  msg3.txt  overlap 380   coincidences 32   kappa 0.0842",
+            ))
+            .stdout(predicate::str::ends_with(
+                "\n3 messages, 3 pairs, 1 likely in depth\n",
+            ))
+            .stderr(predicate::str::contains(
+                "1 pair(s) of messages likely in depth",
+            ));
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("analyze")
+            .arg("depth")
+            .arg(&dir)
+            .arg("--radiogram")
+            .arg("--numbering")
+            .arg("lines")
+            .arg("--all");
+        cmd.assert()
+            .failure()
+            .stdout(predicate::str::contains(
+                "\nok     msg1.txt  msg2.txt  overlap 380",
+            ))
+            .stdout(predicate::str::contains(
+                "\nok     msg2.txt  msg3.txt  overlap 380",
+            ));
+        // z-score 3.8 is below a stricter threshold
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("analyze")
+            .arg("depth")
+            .arg(&dir)
+            .arg("--radiogram")
+            .arg("--numbering")
+            .arg("lines")
+            .arg("--threshold")
+            .arg("4");
+        cmd.assert()
+            .success()
+            .stdout("3 messages, 3 pairs, 0 likely in depth\n");
+        // without the layout options the radiogram header isn't ciphertext
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("analyze").arg("depth").arg(&dir);
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "msg3.txt: string contains invalid symbols: '1' at position 3",
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_analyze_depth_none() {
+        let dir = depth_dir(
+            "solitaire_cypher_cli_test_no_depth",
+            &[
+                (
+                    LINCOLN_FIRST,
+                    "lincoln",
+                    &["--indicator", "--indicator-letters", "AAAAB"],
+                ),
+                (
+                    LINCOLN_SECOND,
+                    "lincoln",
+                    &["--indicator", "--indicator-letters", "AAAAC"],
+                ),
+            ],
+        );
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("analyze").arg("depth").arg(&dir);
+        cmd.assert()
+            .success()
+            .stdout("2 messages, 1 pairs, 0 likely in depth\n");
+        // the indicators aren't message text
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("analyze").arg("depth").arg(&dir).arg("--all");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("overlap 385"));
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("analyze")
+            .arg("depth")
+            .arg(&dir)
+            .arg("--all")
+            .arg("--indicator");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("overlap 380"));
+        let mut cmd = Command::cargo_bin("solitaire_cypher_cli")
+            .expect("crate binary should be where expected");
+        cmd.arg("analyze")
+            .arg("depth")
+            .arg(&dir)
+            .arg("--indicator")
+            .arg("--indicator-length")
+            .arg("1000");
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "too short for its message indicator",
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }